@@ -20,6 +20,12 @@ use std::path::PathBuf;
 
 use structopt::StructOpt;
 
+use crate::{
+    export,
+    import::{Conflict, Format},
+    shell::Shell,
+};
+
 /// Command line options
 #[derive(StructOpt, Debug)]
 #[structopt(setting = structopt::clap::AppSettings::TrailingVarArg)]
@@ -29,7 +35,7 @@ pub struct Opt {
     pub sub_cmd: Option<SubOpt>,
 
     /// Optional configuration path
-    #[structopt(global = true, short = "f", long)]
+    #[structopt(global = true, short = "f", long, alias = "config")]
     pub config_path: Option<PathBuf>,
 
     /// Debug option
@@ -39,6 +45,10 @@ pub struct Opt {
     /// Not run the command. Useful for debugging
     #[structopt(global = true, short, long)]
     pub no_op: bool,
+
+    /// Run aliases that ask for confirmation without asking, like `DIPSE_ASSUME_YES=1`
+    #[structopt(global = true, short, long)]
+    pub yes: bool,
 }
 
 /// Subcommand, CRUD or alias
@@ -54,6 +64,108 @@ pub enum SubOpt {
     /// Init
     Init,
 
+    /// Import aliases from another tool into the section for the current directory
+    Import {
+        /// Format to import: package.json, makefile, vscode, justfile or bashrc
+        #[structopt(long = "from")]
+        from: Format,
+        /// File to import, defaults to the usual file of the format
+        path: Option<PathBuf>,
+        /// What to do with names that are taken: skip, overwrite or prefix
+        #[structopt(long, default_value = "skip")]
+        on_conflict: Conflict,
+        /// Prefix for taken names with `--on-conflict prefix`, like `npm-` for package.json
+        #[structopt(long)]
+        prefix: Option<String>,
+    },
+
+    /// Allow the config files for the current directory to run aliases
+    ///
+    /// Only the text of the config files is checked. Scripts in `scripts_dir` and the files
+    /// aliases are discovered from can still change without a new `allow`.
+    Allow,
+
+    /// Show the changes made to the aliases of the config files for the current directory
+    History {
+        /// Show the changes of every config file
+        #[structopt(long)]
+        all: bool,
+    },
+
+    /// Undo the last changes to the config files for the current directory
+    Undo {
+        /// Number of changes to undo
+        #[structopt(default_value = "1")]
+        n: usize,
+    },
+
+    /// List sections whose directory no longer exists
+    Gc {
+        /// Remove the stale sections from their config files
+        #[structopt(long)]
+        remove: bool,
+    },
+
+    /// Point the sections of a moved directory, and of the directories inside it, to the new path
+    Relocate {
+        /// Where the directory used to be
+        old: PathBuf,
+        /// Where the directory is now
+        new: PathBuf,
+        /// Only show which sections would change
+        #[structopt(long)]
+        dry_run: bool,
+    },
+
+    /// Print the aliases of the current directory in the format of another tool
+    Export {
+        /// make, just, sh, fish, vscode-tasks or json
+        #[structopt(long)]
+        format: export::Format,
+    },
+
+    /// Print the hook that turns aliases into shell functions, for your shell's rc file
+    ShellInit {
+        /// bash, zsh or fish
+        shell: Shell,
+    },
+
+    /// Shell code for the hook after a directory change, used by `shell-init`
+    #[structopt(name = "__hook", setting = structopt::clap::AppSettings::Hidden)]
+    Hook {
+        /// bash, zsh or fish
+        shell: Shell,
+        /// Previous directory
+        #[structopt(long)]
+        from: Option<PathBuf>,
+        /// Functions defined for the previous directory, separated by spaces
+        #[structopt(long, default_value = "")]
+        funcs: String,
+    },
+
+    /// Print the completion script for bash, zsh or fish
+    Completions {
+        /// bash, zsh or fish
+        shell: Shell,
+    },
+
+    /// Completion candidates for the words after `dipse`, used by `completions`
+    #[structopt(name = "__complete", setting = structopt::clap::AppSettings::Hidden)]
+    Complete {
+        /// Words of the command line, the last one is completed
+        #[structopt(last = true)]
+        words: Vec<String>,
+    },
+
+    /// Explain how an alias is resolved for the current directory
+    Which {
+        /// Name of alias
+        name: String,
+        /// Arguments that would be passed to the alias
+        #[structopt(last = true)]
+        args: Vec<String>,
+    },
+
     /// Alias
     #[structopt(external_subcommand)]
     Other(Vec<String>),
@@ -68,12 +180,36 @@ pub enum Crud {
         name: String,
         /// Command to map to
         cmd: String,
+        /// Directory of the section to add to, instead of the one for current dir
+        #[structopt(long)]
+        section: Option<PathBuf>,
+        /// Add to the global config
+        #[structopt(long)]
+        global: bool,
+        /// Create the section if the config file has none for the directory
+        #[structopt(long)]
+        create_section: bool,
     },
 
     /// List all entries for current dir
     List {
         /// Name of alias, if not provided, whole list is shown
         name: Option<String>,
+        /// Print JSON
+        #[structopt(long, conflicts_with = "toml")]
+        json: bool,
+        /// Print TOML
+        #[structopt(long)]
+        toml: bool,
+        /// Show every section of the config files, not only the aliases for current dir
+        #[structopt(long, conflicts_with = "name")]
+        all: bool,
+        /// Show where each alias is defined and which aliases it shadows
+        #[structopt(long)]
+        origin: bool,
+        /// Keep the order of the config file instead of sorting by name
+        #[structopt(long)]
+        declared: bool,
     },
 
     /// Update given alias
@@ -89,4 +225,36 @@ pub enum Crud {
         /// Name of alias
         name: String,
     },
+
+    /// Give an alias a new name
+    Rename {
+        /// Name of alias
+        name: String,
+        /// New name
+        new_name: String,
+    },
+
+    /// Copy an alias to another section or project
+    Copy {
+        /// Name of alias
+        name: String,
+        /// Section key of the same config file, or a directory whose section gets the alias
+        #[structopt(long, required_unless = "to-global")]
+        to: Option<String>,
+        /// Section for every directory (`"/"`) of the global config
+        #[structopt(long, conflicts_with = "to")]
+        to_global: bool,
+    },
+
+    /// Move an alias to another section or project
+    Move {
+        /// Name of alias
+        name: String,
+        /// Section key of the same config file, or a directory whose section gets the alias
+        #[structopt(long, required_unless = "to-global")]
+        to: Option<String>,
+        /// Section for every directory (`"/"`) of the global config
+        #[structopt(long, conflicts_with = "to")]
+        to_global: bool,
+    },
 }
@@ -0,0 +1,325 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Dynamic shell completion
+//!
+//! The scripts printed by `dipse completions <shell>` call `dipse __complete -- <words>` with
+//! the words of the command line after `dipse`, the last one being the word under the cursor.
+//! It prints one candidate per line, followed by a tab and its description. No candidates
+//! lets the shell complete file names.
+
+use crate::{args::Opt, export, import, model::Alias, resolver::Resolution, shell::Shell};
+use std::path::PathBuf;
+use structopt::{clap::AppSettings, StructOpt};
+
+/// Subcommands and their descriptions, as [`Opt`] defines them, without the hidden ones
+fn subcommands() -> Vec<(String, String)> {
+    Opt::clap()
+        .p
+        .subcommands
+        .iter()
+        .filter(|s| !s.p.is_set(AppSettings::Hidden))
+        .map(|s| {
+            let about = s.p.meta.about.unwrap_or_default();
+            (
+                s.p.meta.name.clone(),
+                about.lines().next().unwrap_or_default().to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Options taking a value
+const VALUE_OPTIONS: &[&str] = &[
+    "-f",
+    "--config-path",
+    "--config",
+    "--from",
+    "--format",
+    "--on-conflict",
+    "--prefix",
+    "--to",
+    "--section",
+];
+
+/// Command line being completed
+#[derive(Debug, Default)]
+pub struct Line {
+    /// Config file given with `-f`
+    pub config_path: Option<PathBuf>,
+    /// Words before the cursor that are not options
+    pub args: Vec<String>,
+    /// Whether `--` comes before the cursor
+    pub after_dashes: bool,
+    /// Option whose value is under the cursor
+    pub option: Option<String>,
+    /// Word under the cursor
+    pub current: String,
+}
+
+impl Line {
+    /// Split the words after `dipse`, the last one is the word under the cursor
+    pub fn parse(words: &[String]) -> Self {
+        let mut line = Line::default();
+        let (current, words) = match words.split_last() {
+            Some((c, w)) => (c.clone(), w),
+            None => (String::new(), &[][..]),
+        };
+        line.current = current;
+
+        let mut words = words.iter();
+        while let Some(word) = words.next() {
+            if line.after_dashes {
+                line.args.push(word.clone());
+            } else if word == "--" {
+                line.after_dashes = true;
+            } else if VALUE_OPTIONS.contains(&word.as_str()) {
+                match words.next() {
+                    Some(v) if matches!(word.as_str(), "-f" | "--config-path" | "--config") => {
+                        line.config_path = Some(PathBuf::from(v))
+                    }
+                    Some(_) => {}
+                    None => line.option = Some(word.clone()),
+                }
+            } else if !word.starts_with('-') {
+                line.args.push(word.clone());
+            }
+        }
+        line
+    }
+}
+
+/// Candidates for the word under the cursor, with descriptions
+///
+/// `resolution` is `None` when there is no config for the current directory.
+pub fn candidates(line: &Line, resolution: Option<&Resolution>) -> Vec<(String, String)> {
+    let aliases = || -> Vec<(String, String)> {
+        match resolution {
+            Some(r) => r
+                .aliases()
+                .into_values()
+                .map(|a| (a.name.clone(), describe(a)))
+                .collect(),
+            None => vec![],
+        }
+    };
+    let names = |names: &[&str]| -> Vec<(String, String)> {
+        names
+            .iter()
+            .map(|n| (n.to_string(), String::new()))
+            .collect()
+    };
+
+    let mut candidates = match (
+        line.option.as_deref(),
+        line.args.first().map(|a| a.as_str()),
+    ) {
+        (Some("--format"), _) => names(export::Format::NAMES),
+        (Some("--from"), _) => names(import::Format::NAMES),
+        (Some("--on-conflict"), _) => names(&["skip", "overwrite", "prefix"]),
+        (Some(_), _) => vec![],
+        _ if line.current.starts_with('-') => vec![],
+        (None, None) => {
+            let mut c = subcommands();
+            c.extend(aliases());
+            c
+        }
+        (None, Some("list"))
+        | (None, Some("which"))
+        | (None, Some("copy"))
+        | (None, Some("move"))
+            if line.args.len() == 1 =>
+        {
+            aliases()
+        }
+        (None, Some("update")) | (None, Some("delete")) | (None, Some("rename"))
+            if line.args.len() == 1 =>
+        {
+            match resolution.and_then(|r| r.best().ok()) {
+                Some(best) => best
+                    .section
+                    .aliases
+                    .iter()
+                    .map(|a| (a.name.clone(), describe(a)))
+                    .collect(),
+                None => vec![],
+            }
+        }
+        (None, Some("shell-init")) | (None, Some("completions")) if line.args.len() == 1 => {
+            names(Shell::NAMES)
+        }
+        (None, Some(first)) if subcommands().iter().any(|(n, _)| n == first) => vec![],
+        // Any number of aliases can be run at once, up to the arguments after `--`
+        (None, Some(_)) if line.after_dashes => vec![],
+        (None, Some(_)) => aliases(),
+    };
+
+    candidates.retain(|(name, _)| name.starts_with(&line.current));
+    candidates
+}
+
+/// One line description of an alias
+fn describe(alias: &Alias) -> String {
+    let text = match &alias.description {
+        Some(d) => d.clone(),
+        None => alias.summary(),
+    };
+    text.lines().next().unwrap_or_default().replace('\t', " ")
+}
+
+/// Completion script for `shell`
+pub fn completion_script(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => r#"_dipse() {
+    local IFS=$'\n'
+    COMPREPLY=($(command dipse __complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null | cut -f1))
+}
+complete -o default -F _dipse dipse
+"#
+        .to_string(),
+        Shell::Zsh => r#"#compdef dipse
+_dipse() {
+    local -a candidates
+    local line
+    for line in "${(@f)$(command dipse __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)}"; do
+        [[ -n $line ]] || continue
+        candidates+=("${${line%%$'\t'*}//:/\\:}:${line#*$'\t'}")
+    done
+    if (( ${#candidates} )); then
+        _describe 'dipse' candidates
+    else
+        _files
+    fi
+}
+if [ "$funcstack[1]" = "_dipse" ]; then
+    _dipse "$@"
+else
+    compdef _dipse dipse
+fi
+"#
+        .to_string(),
+        Shell::Fish => r#"function __dipse_complete
+    set -l words (commandline -opc)
+    set -l current (commandline -ct)
+    command dipse __complete -- $words[2..-1] "$current" 2>/dev/null
+end
+complete -c dipse -f -a '(__dipse_complete)'
+complete -c dipse -s f -l config-path -r -F
+complete -c dipse -n '__fish_seen_subcommand_from import' -F
+"#
+        .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::Config, parser::parse_config, resolver::Candidate};
+    use std::path::Path;
+
+    fn words(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn config() -> Config {
+        let text = "[sub]\nbuild = \"make\"\n[\".\"]\nbench = { cmd = \"cargo bench\", description = \"Run\\tbenches\" }\n";
+        parse_config(Path::new("/project/.d.toml"), text).unwrap()
+    }
+
+    /// Resolution of `/project/sub` with every section of `config`, in file order
+    fn resolution(config: &Config) -> Resolution<'_> {
+        Resolution {
+            pwd: PathBuf::from("/project/sub"),
+            candidates: config
+                .sections
+                .iter()
+                .map(|section| Candidate {
+                    config,
+                    section,
+                    path: Path::new("/project").join(&section.key),
+                    discovered: vec![],
+                })
+                .collect(),
+            warnings: vec![],
+        }
+    }
+
+    fn names(line: &[&str], resolution: Option<&Resolution>) -> Vec<String> {
+        candidates(&Line::parse(&words(line)), resolution)
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect()
+    }
+
+    #[test]
+    fn line_parse_splits_options_arguments_and_the_current_word() {
+        let line = Line::parse(&words(&["-f", "x.toml", "copy", "-n", "b", "--to", ""]));
+        assert_eq!(line.config_path, Some(PathBuf::from("x.toml")));
+        assert_eq!(line.args, ["copy", "b"]);
+        assert_eq!(line.option.as_deref(), Some("--to"));
+        assert_eq!(line.current, "");
+
+        let line = Line::parse(&words(&["build", "--", "--release", "x"]));
+        assert_eq!(line.args, ["build", "--release"]);
+        assert!(line.after_dashes);
+        assert_eq!(line.current, "x");
+    }
+
+    #[test]
+    fn subcommands_come_from_the_arguments_without_hidden_ones() {
+        let subcommands = subcommands();
+        let names = subcommands
+            .iter()
+            .map(|(n, _)| n.as_str())
+            .collect::<Vec<_>>();
+        for name in [
+            "add",
+            "list",
+            "copy",
+            "gc",
+            "relocate",
+            "which",
+            "shell-init",
+        ] {
+            assert!(names.contains(&name), "{} is missing", name);
+        }
+        assert!(!names.iter().any(|n| n.starts_with("__")));
+        assert!(subcommands.iter().all(|(_, about)| !about.is_empty()));
+    }
+
+    #[test]
+    fn candidates_follow_the_position_on_the_line() {
+        let config = config();
+        let resolution = resolution(&config);
+        let r = Some(&resolution);
+
+        assert_eq!(names(&["b"], r), ["bench", "build"]);
+        assert!(names(&[""], r).contains(&"add".to_string()));
+        assert_eq!(names(&["export", "--format", "j"], r), ["just", "json"]);
+        assert_eq!(names(&["shell-init", "f"], r), ["fish"]);
+        // Only the aliases of the nearest section can be changed
+        assert_eq!(names(&["update", ""], r), ["build"]);
+        assert!(names(&["add", ""], r).is_empty());
+        assert_eq!(names(&["build", "b"], r), ["bench", "build"]);
+        assert!(names(&["build", "--", "b"], r).is_empty());
+        assert!(names(&["b"], None).is_empty());
+
+        let all = candidates(&Line::parse(&words(&["ben"])), r);
+        assert_eq!(all, [("bench".to_string(), "Run benches".to_string())]);
+    }
+}
@@ -16,11 +16,29 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::error::Error;
-use std::path::PathBuf;
+use crate::{
+    error::Error,
+    history::{self, Change, Entry},
+    model::{Alias, Config},
+    resolver::{section_dir, Resolver},
+    trust::sha256,
+    utils::normalize,
+};
+use std::{
+    env,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// How long to wait for another `dipse` to release a config file
+const LOCK_WAIT: Duration = Duration::from_secs(5);
+/// Number of backups kept next to a config file
+const BACKUPS: usize = 3;
 
 // Returns .d.toml if it exists in current directory or any parent dir
-// Or the XDG spec config/dipse/d.toml
 fn traverse_upwards_for_config(path: &mut PathBuf) -> Option<PathBuf> {
     let current_dir_config = path.join(".d.toml");
 
@@ -46,3 +64,1115 @@ pub fn get_config_path() -> Result<Option<PathBuf>, Error> {
     };
     Ok(traverse_upwards_for_config(&mut curr_path))
 }
+
+/// Path of the global config file, `$XDG_CONFIG_HOME/dipse/d.toml`
+/// Error:
+/// - Config directory could not be found
+pub fn get_global_config_path() -> Result<PathBuf, Error> {
+    match dirs::config_dir() {
+        Some(d) => Ok(d.join("dipse").join("d.toml")),
+        None => Err(Error::ConfigDir),
+    }
+}
+
+/// Create an empty `.d.toml` with a section for `dir`
+/// Error:
+/// - Could not create or write the file
+pub fn init_config(dir: &Path) -> Result<PathBuf, Error> {
+    let new_p = dir.join(".d.toml");
+    let mut f = match File::create(&new_p) {
+        Err(e) => return Err(Error::ConfigFileCreation(new_p, e)),
+        Ok(e) => e,
+    };
+
+    if let Err(e) = writeln!(f, "[\"{}\"]", dir.display()) {
+        return Err(Error::ConfigFileWrite(new_p, e));
+    }
+
+    if let Err(e) = f.flush() {
+        return Err(Error::ConfigFileWrite(new_p, e));
+    }
+
+    Ok(new_p)
+}
+
+/// Replace the file at `path` with `contents`, without ever leaving a half written file
+///
+/// The contents go to a temporary file in the same directory, which is renamed over `path`.
+/// Symlinks are followed, so a config file linked from a dotfiles repository stays a link.
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let path = match fs::canonicalize(path) {
+        Ok(p) => p,
+        Err(_) => path.to_path_buf(),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+
+    let write = || -> io::Result<()> {
+        let mut f = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        if let Ok(meta) = fs::metadata(&path) {
+            f.set_permissions(meta.permissions())?;
+        }
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+        fs::rename(&tmp, &path)
+    };
+    let res = write();
+    if res.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    res
+}
+
+/// File next to the config file at `path`, with `suffix` appended to its name
+///
+/// Symlinks are followed like [`write_atomic`] does.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let path = match fs::canonicalize(path) {
+        Ok(p) => p,
+        Err(_) => path.to_path_buf(),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}{}", name, suffix))
+}
+
+/// Copy the config file at `path` to `<name>.bak` before it is replaced
+///
+/// Older backups are kept as `<name>.bak.1` up to `<name>.bak.2`, the oldest is dropped. Nothing
+/// happens if the file does not exist yet.
+pub fn backup(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let name = |n: usize| match n {
+        0 => sibling(path, ".bak"),
+        n => sibling(path, &format!(".bak.{}", n)),
+    };
+    for n in (1..BACKUPS).rev() {
+        match fs::rename(name(n - 1), name(n)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::copy(path, name(0))?;
+    Ok(())
+}
+
+/// Lock file for the config file at `path`, `dipse/locks/<hash>.lock` in the runtime directory
+///
+/// The hash is the SHA-256 of the canonical path, also for files that do not exist yet. Without
+/// a runtime directory the cache directory is used, then the temporary directory.
+fn lock_path(path: &Path) -> PathBuf {
+    let canonical = match (fs::canonicalize(path), path.parent(), path.file_name()) {
+        (Ok(p), _, _) => p,
+        (Err(_), Some(dir), Some(name)) => match fs::canonicalize(dir) {
+            Ok(d) => d.join(name),
+            Err(_) => path.to_path_buf(),
+        },
+        _ => path.to_path_buf(),
+    };
+    let dir = dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .unwrap_or_else(env::temp_dir);
+    dir.join("dipse")
+        .join("locks")
+        .join(format!("{}.lock", sha256(&canonical.to_string_lossy())))
+}
+
+/// Advisory lock on a config file, held from reading it until the changes are written
+///
+/// The lock is an exclusive `flock` on a file in the runtime directory, see [`lock_path`], so
+/// nothing shows up next to the config file. The operating system releases it when the lock is
+/// dropped or `dipse` dies, the file itself is left in place. Only `dipse` itself honours it.
+#[derive(Debug)]
+pub struct Lock {
+    /// Config file the lock is for
+    pub config: PathBuf,
+    /// The locked file
+    _file: File,
+}
+
+impl Lock {
+    /// Wait for the lock on the config file at `path`
+    /// Error:
+    /// - Another `dipse` holds the lock for too long
+    /// - The lock file could not be opened or locked
+    pub fn acquire(path: &Path) -> Result<Lock, Error> {
+        Lock::acquire_within(path, LOCK_WAIT)
+    }
+
+    fn acquire_within(path: &Path, wait: Duration) -> Result<Lock, Error> {
+        let lock_path = lock_path(path);
+        let config = match fs::canonicalize(path) {
+            Ok(p) => p,
+            Err(_) => path.to_path_buf(),
+        };
+        if let Some(dir) = lock_path.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                return Err(Error::ConfigFileWrite(path.to_path_buf(), e));
+            }
+        }
+        let file = match OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_path)
+        {
+            Err(e) => return Err(Error::ConfigFileWrite(path.to_path_buf(), e)),
+            Ok(f) => f,
+        };
+        let start = SystemTime::now();
+
+        loop {
+            match file.try_lock() {
+                Ok(()) => {
+                    return Ok(Lock {
+                        config,
+                        _file: file,
+                    })
+                }
+                Err(TryLockError::Error(e)) => {
+                    return Err(Error::ConfigFileWrite(path.to_path_buf(), e))
+                }
+                Err(TryLockError::WouldBlock) => {}
+            }
+            if start.elapsed().unwrap_or_default() > wait {
+                let msg = format!("{} is held by another dipse", lock_path.display());
+                return Err(Error::ConfigFileWrite(
+                    path.to_path_buf(),
+                    io::Error::new(ErrorKind::WouldBlock, msg),
+                ));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+/// Lock the files [`ConfigSet::load`] reads for `dir`, skipping the ones in `locks`
+/// Error:
+/// - A file could not be locked
+pub fn lock_configs(
+    locks: &mut Vec<Lock>,
+    config_path: Option<&Path>,
+    dir: &Path,
+) -> Result<(), Error> {
+    let paths = match config_path {
+        Some(c) => vec![c.to_path_buf()],
+        None => ConfigSet::discover_paths(dir)?,
+    };
+    for path in paths {
+        lock_config(locks, &path)?;
+    }
+    Ok(())
+}
+
+/// Lock the file at `path` unless it is in `locks` already
+/// Error:
+/// - The file could not be locked
+pub fn lock_config(locks: &mut Vec<Lock>, path: &Path) -> Result<(), Error> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if !locks.iter().any(|l| l.config == canonical) {
+        locks.push(Lock::acquire(path)?);
+    }
+    Ok(())
+}
+
+/// All config files that apply to a directory
+///
+/// The nearest `.d.toml` comes first, followed by the global config. Sections of earlier files
+/// win over sections of later files for the same directory.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSet {
+    /// Loaded config files, in priority order
+    pub files: Vec<Config>,
+}
+
+impl ConfigSet {
+    /// Load the nearest `.d.toml` above `dir` and the global config, if they exist
+    /// Error:
+    /// - No config file found
+    /// - A config file could not be read or parsed
+    pub fn discover(dir: &Path) -> Result<Self, Error> {
+        let mut files = vec![];
+        for path in ConfigSet::discover_paths(dir)? {
+            files.push(Config::load(path)?);
+        }
+
+        if files.is_empty() {
+            return Err(Error::NoConfigFile);
+        }
+
+        Ok(ConfigSet { files })
+    }
+
+    /// Paths of the files [`ConfigSet::discover`] loads for `dir`
+    /// Error:
+    /// - The directory can not be canonicalized
+    pub fn discover_paths(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut paths = vec![];
+
+        let mut dir = match dir.canonicalize() {
+            Err(e) => return Err(Error::ConfigPath(e)),
+            Ok(d) => d,
+        };
+        if let Some(p) = traverse_upwards_for_config(&mut dir) {
+            paths.push(p);
+        }
+
+        if let Ok(global) = get_global_config_path() {
+            if global.exists() {
+                paths.push(global);
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Load the config file given with `-f`, or discover the ones for `dir`
+    /// Error:
+    /// - No config file found
+    /// - A config file could not be read or parsed
+    pub fn load(config_path: Option<PathBuf>, dir: &Path) -> Result<Self, Error> {
+        match config_path {
+            Some(c) => ConfigSet::from_path(c),
+            None => ConfigSet::discover(dir),
+        }
+    }
+
+    /// Load only the config file at `path`
+    /// Error:
+    /// - The config file could not be read or parsed
+    pub fn from_path(path: PathBuf) -> Result<Self, Error> {
+        Ok(ConfigSet {
+            files: vec![Config::load(path)?],
+        })
+    }
+
+    /// Canonical paths of the files, in the same order, as the history stores them
+    pub fn canonical_paths(&self) -> Vec<PathBuf> {
+        self.files
+            .iter()
+            .map(|f| f.path.canonicalize().unwrap_or_else(|_| f.path.clone()))
+            .collect()
+    }
+
+    /// Save the files at `paths`, each once
+    /// Error:
+    /// - A file could not be written
+    pub fn save_changed<'a>(&self, paths: impl Iterator<Item = &'a Path>) -> Result<(), Error> {
+        let mut saved: Vec<&Path> = vec![];
+        for path in paths {
+            if saved.contains(&path) {
+                continue;
+            }
+            if let Some(config) = self.files.iter().find(|f| f.path == path) {
+                config.save()?;
+            }
+            saved.push(path);
+        }
+        Ok(())
+    }
+
+    /// Config file that owns `path`
+    pub fn file_mut(&mut self, path: &Path) -> Option<&mut Config> {
+        self.files.iter_mut().find(|f| f.path == path)
+    }
+
+    /// Config file and section key a new alias for `dir` goes to
+    ///
+    /// This is the most specific section for `dir` of any file, with `exact` the section of
+    /// exactly `dir`. If there is none and `create` is set, a section is added to the first file,
+    /// with a key relative to the file unless it is the global config.
+    /// Error:
+    /// - There is no section for the directory and `create` is not set
+    /// - A config file path or `dir` can not be canonicalized
+    pub fn add_target(
+        &mut self,
+        dir: &Path,
+        exact: bool,
+        create: bool,
+    ) -> Result<(PathBuf, PathBuf), Error> {
+        // A file that is not written yet has no sections to find
+        let found = match self.files.iter().all(|f| f.path.exists()) {
+            false => None,
+            true => Resolver::new(self)
+                .resolve(dir)?
+                .candidates
+                .iter()
+                .find(|c| !exact || c.path == dir)
+                .map(|c| (c.config.path.clone(), c.section.key.clone())),
+        };
+        if let Some(target) = found {
+            return Ok(target);
+        }
+
+        // The nearest `.d.toml` comes first, the global config is only used if there is none
+        let config = match (create, self.files.first_mut()) {
+            (true, Some(c)) => c,
+            _ => return Err(Error::NoConfigForPath(dir.to_path_buf())),
+        };
+        let is_global = get_global_config_path().is_ok_and(|g| g == config.path);
+        let config_dir = config.dir().canonicalize().unwrap_or_default();
+        let key = match dir.strip_prefix(&config_dir) {
+            Ok(rel) if !is_global && rel.as_os_str().is_empty() => PathBuf::from("."),
+            Ok(rel) if !is_global => rel.to_path_buf(),
+            _ => dir.to_path_buf(),
+        };
+        config.add_section(&key)?;
+        Ok((config.path.clone(), key))
+    }
+
+    /// Copy `alias` from the section `from` to the section `to`, removing it from `from` if
+    /// `remove` is set
+    ///
+    /// Both are a config file of the set and a section key, with `create` the section `to` is
+    /// added if it is missing. Nothing is saved, save the target file first so a failure never
+    /// loses the alias. Returns the alias as written to `to`.
+    /// Error:
+    /// - The alias is discovered and would have to be removed
+    /// - A file is not in the set
+    /// - The target section does not exist or already has the alias
+    pub fn transfer(
+        &mut self,
+        alias: &Alias,
+        from: (&Path, &Path),
+        to: (&Path, &Path),
+        remove: bool,
+        create: bool,
+    ) -> Result<Alias, Error> {
+        if alias.discovered && remove {
+            return Err(Error::Discovered(alias.name.clone(), alias.source.clone()));
+        }
+        let alias = Alias {
+            discovered: false,
+            source: Default::default(),
+            file: None,
+            ..alias.clone()
+        };
+
+        let target = match self.file_mut(to.0) {
+            None => return Err(Error::NoConfigForPath(to.0.to_path_buf())),
+            Some(t) => t,
+        };
+        if create {
+            target.add_section(to.1)?;
+        }
+        target.add(to.1, alias.clone())?;
+        if remove {
+            match self.file_mut(from.0) {
+                None => return Err(Error::NoConfigForPath(from.0.to_path_buf())),
+                Some(source) => {
+                    source.delete(from.1, &alias.name)?;
+                }
+            }
+        }
+        Ok(alias)
+    }
+
+    /// Sections whose directory can not be accessed, as config file, key and the reason
+    pub fn stale_sections(&self) -> Vec<(PathBuf, PathBuf, io::Error)> {
+        let mut stale = vec![];
+        for config in &self.files {
+            for section in &config.sections {
+                if let Err(e) = section_dir(config.dir(), &section.key) {
+                    stale.push((config.path.clone(), section.key.clone(), e));
+                }
+            }
+        }
+        stale
+    }
+
+    /// Remove the sections whose directory no longer exists
+    ///
+    /// Sections that can not be accessed for other reasons, like permissions, are kept.
+    /// Returns the config file and key of every removed section.
+    /// Error:
+    /// - A section could not be removed from its file
+    pub fn remove_stale(&mut self) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+        let mut removed = vec![];
+        for (config, key, e) in self.stale_sections() {
+            if e.kind() != ErrorKind::NotFound {
+                continue;
+            }
+            if let Some(file) = self.file_mut(&config) {
+                file.remove_section(&key)?;
+                removed.push((config, key));
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Sections for `old` or a directory below it, with the key they get when `old` moves to `new`
+    ///
+    /// `old` and `new` are absolute. Relative keys stay relative as long as the file contains
+    /// the new directory. Returns the config file, the current key and the new key.
+    pub fn relocations(&self, old: &Path, new: &Path) -> Vec<(PathBuf, PathBuf, PathBuf)> {
+        let mut renames = vec![];
+        for config in &self.files {
+            let config_dir = match config.dir().canonicalize() {
+                Ok(d) => d,
+                Err(_) => normalize(config.dir()),
+            };
+            for section in &config.sections {
+                let dir = normalize(&config_dir.join(&section.key));
+                let rest = match dir.strip_prefix(old) {
+                    Ok(r) => r,
+                    Err(_) => continue,
+                };
+                let new_dir = match rest.as_os_str().is_empty() {
+                    true => new.to_path_buf(),
+                    false => new.join(rest),
+                };
+                let key = match new_dir.strip_prefix(&config_dir) {
+                    Ok(r) if section.key.is_relative() && r.as_os_str().is_empty() => {
+                        PathBuf::from(".")
+                    }
+                    Ok(r) if section.key.is_relative() => r.to_path_buf(),
+                    _ => new_dir,
+                };
+                renames.push((config.path.clone(), section.key.clone(), key));
+            }
+        }
+        renames
+    }
+
+    /// Rename the sections returned by [`ConfigSet::relocations`]
+    /// Error:
+    /// - A section could not be renamed
+    pub fn relocate(
+        &mut self,
+        old: &Path,
+        new: &Path,
+    ) -> Result<Vec<(PathBuf, PathBuf, PathBuf)>, Error> {
+        let renames = self.relocations(old, new);
+        for (config, old_key, new_key) in &renames {
+            if let Some(file) = self.file_mut(config) {
+                file.rename_section(old_key, new_key)?;
+            }
+        }
+        Ok(renames)
+    }
+}
+
+/// Add the alias `name` for `pwd`, or for the directory `section` relative to it
+///
+/// The alias goes to the section [`ConfigSet::add_target`] picks, with `global` from the global
+/// config only. `create` adds the section if there is none.
+/// Error:
+/// - The directory does not exist, or there is no section for it and `create` is not set
+/// - The alias exists already
+/// - A config file could not be locked, loaded or saved
+pub fn add_alias(
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    name: &str,
+    cmd: &str,
+    section: Option<&Path>,
+    global: bool,
+    create: bool,
+) -> Result<(), Error> {
+    let dir = match section {
+        Some(s) => match pwd.join(s).canonicalize() {
+            Err(e) => return Err(Error::ConfigPath(e)),
+            Ok(d) => d,
+        },
+        None => match pwd.canonicalize() {
+            Err(_) => return Err(Error::CurrentDir),
+            Ok(d) => d,
+        },
+    };
+
+    let mut locks = vec![];
+    let mut set = if global {
+        let path = get_global_config_path()?;
+        lock_config(&mut locks, &path)?;
+        match path.exists() {
+            true => ConfigSet::from_path(path)?,
+            false => ConfigSet {
+                files: vec![Config {
+                    path,
+                    ..Config::default()
+                }],
+            },
+        }
+    } else {
+        lock_configs(&mut locks, config_path.as_deref(), &dir)?;
+        ConfigSet::load(config_path, &dir)?
+    };
+
+    let (path, key) = set.add_target(&dir, section.is_some(), create)?;
+    let config = set.file_mut(&path).unwrap();
+    let alias = Alias::new(name, cmd);
+    config.add(&key, alias.clone())?;
+    config.save()?;
+    history::record_or_warn(vec![Entry::new(
+        config,
+        &key,
+        Change::Add,
+        None,
+        Some(&alias),
+    )]);
+    Ok(())
+}
+
+/// Lock and load the config files for `pwd`, with the most specific section and the full name
+/// of the alias `name` in it
+/// Error:
+/// - A config file could not be locked or loaded
+/// - No section applies to `pwd` or it does not define the alias
+fn local_alias(
+    locks: &mut Vec<Lock>,
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    name: &str,
+) -> Result<(ConfigSet, PathBuf, PathBuf, String), Error> {
+    lock_configs(locks, config_path.as_deref(), pwd)?;
+    let set = ConfigSet::load(config_path, pwd)?;
+    let (path, key, name) = {
+        let resolution = Resolver::new(&set).resolve(pwd)?;
+        resolution.print_warnings();
+        let best = resolution.best()?;
+        let name = resolution.local_name(name)?;
+        (best.config.path.clone(), best.section.key.clone(), name)
+    };
+    Ok((set, path, key, name))
+}
+
+/// Give the alias `name` of the most specific section for `pwd` the command `cmd`
+/// Error:
+/// - The section does not define the alias
+/// - A config file could not be locked, loaded or saved
+pub fn update_alias(
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    name: &str,
+    cmd: String,
+) -> Result<(), Error> {
+    let mut locks = vec![];
+    let (mut set, path, key, name) = local_alias(&mut locks, config_path, pwd, name)?;
+    let config = set.file_mut(&path).unwrap();
+    let old = config.section(&key).and_then(|s| s.get(&name)).cloned();
+    config.update(&key, &name, cmd)?;
+    config.save()?;
+    let new = config.section(&key).and_then(|s| s.get(&name));
+    history::record_or_warn(vec![Entry::new(
+        config,
+        &key,
+        Change::Update,
+        old.as_ref(),
+        new,
+    )]);
+    Ok(())
+}
+
+/// Remove the alias `name` from the most specific section for `pwd`
+/// Error:
+/// - The section does not define the alias
+/// - A config file could not be locked, loaded or saved
+pub fn delete_alias(config_path: Option<PathBuf>, pwd: &Path, name: &str) -> Result<(), Error> {
+    let mut locks = vec![];
+    let (mut set, path, key, name) = local_alias(&mut locks, config_path, pwd, name)?;
+    let config = set.file_mut(&path).unwrap();
+    let old = config.delete(&key, &name)?;
+    config.save()?;
+    history::record_or_warn(vec![Entry::new(
+        config,
+        &key,
+        Change::Delete,
+        Some(&old),
+        None,
+    )]);
+    Ok(())
+}
+
+/// Give the alias `name` of the most specific section for `pwd` the name `new_name`
+/// Error:
+/// - The section does not define the alias, or `new_name` is taken
+/// - A config file could not be locked, loaded or saved
+pub fn rename_alias(
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    name: &str,
+    new_name: &str,
+) -> Result<(), Error> {
+    let mut locks = vec![];
+    let (mut set, path, key, name) = local_alias(&mut locks, config_path, pwd, name)?;
+    let config = set.file_mut(&path).unwrap();
+    let old = config.section(&key).and_then(|s| s.get(&name)).cloned();
+    config.rename(&key, &name, new_name)?;
+    config.save()?;
+    let new = config.section(&key).and_then(|s| s.get(new_name));
+    history::record_or_warn(vec![Entry::new(
+        config,
+        &key,
+        Change::Rename,
+        old.as_ref(),
+        new,
+    )]);
+    Ok(())
+}
+
+/// Copy the alias `name` to the section `to` with [`ConfigSet::transfer`], removing the original
+/// if `remove` is set
+///
+/// `to` is a section key of the same file or a directory, `None` is the `"/"` section of the
+/// global config. Returns a line describing what happened.
+/// Error:
+/// - The alias or the target section does not exist
+/// - A config file could not be locked, read or written
+pub fn transfer_alias(
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    name: &str,
+    to: Option<String>,
+    remove: bool,
+) -> Result<String, Error> {
+    let mut locks = vec![];
+    lock_configs(&mut locks, config_path.as_deref(), pwd)?;
+    let mut set = ConfigSet::load(config_path.clone(), pwd)?;
+
+    let (alias, from_path, from_key) = {
+        let resolution = Resolver::new(&set).resolve(pwd)?;
+        resolution.print_warnings();
+        let (candidate, alias) = resolution.get(name)?;
+        (
+            alias.clone(),
+            candidate.config.path.clone(),
+            candidate.section.key.clone(),
+        )
+    };
+
+    // Target as a config file and a section key
+    let same_file_key = |set: &ConfigSet, to: &str| {
+        set.files
+            .iter()
+            .any(|f| f.path == from_path && f.section(Path::new(to)).is_some())
+    };
+    let (to_path, to_key) = match &to {
+        None => (get_global_config_path()?, PathBuf::from("/")),
+        Some(to) if same_file_key(&set, to) => (from_path.clone(), PathBuf::from(to)),
+        Some(to) => {
+            let dir = pwd.join(to);
+            let target_set = ConfigSet::load(config_path, &dir)?;
+            let resolution = Resolver::new(&target_set).resolve(&dir)?;
+            let best = resolution.best()?;
+            (best.config.path.clone(), best.section.key.clone())
+        }
+    };
+    lock_config(&mut locks, &to_path)?;
+
+    // The target file joins the set unless it is one of its files already
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let to_path = match set
+        .files
+        .iter()
+        .find(|f| canonical(&f.path) == canonical(&to_path))
+    {
+        Some(f) => f.path.clone(),
+        None => {
+            let config = match to_path.exists() {
+                true => Config::load(to_path.clone())?,
+                false => Config {
+                    path: to_path.clone(),
+                    ..Config::default()
+                },
+            };
+            set.files.push(config);
+            to_path
+        }
+    };
+
+    let alias = set.transfer(
+        &alias,
+        (&from_path, &from_key),
+        (&to_path, &to_key),
+        remove,
+        to.is_none(),
+    )?;
+    let mut changed = vec![to_path.as_path()];
+    if remove {
+        changed.push(from_path.as_path());
+    }
+    set.save_changed(changed.into_iter())?;
+
+    // A copy is an add to the target, a move also deletes from the source
+    let file = |path: &Path| set.files.iter().find(|f| f.path == path).unwrap();
+    let mut entries = vec![Entry::new(
+        file(&to_path),
+        &to_key,
+        Change::Add,
+        None,
+        Some(&alias),
+    )];
+    if remove {
+        entries.push(Entry::new(
+            file(&from_path),
+            &from_key,
+            Change::Delete,
+            Some(&alias),
+            None,
+        ));
+    }
+    history::record_or_warn(entries);
+
+    Ok(format!(
+        "{} {} to {:?} in {}",
+        if remove { "moved" } else { "copied" },
+        alias.name,
+        to_key,
+        to_path.display()
+    ))
+}
+
+/// Report the sections whose directory can not be accessed, removing the missing ones if
+/// `remove` is set
+///
+/// Returns a line for every stale section, or a note that there are none.
+/// Error:
+/// - A config file could not be locked, loaded or saved
+pub fn gc_sections(
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    remove: bool,
+) -> Result<Vec<String>, Error> {
+    let mut locks = vec![];
+    if remove {
+        lock_configs(&mut locks, config_path.as_deref(), pwd)?;
+    }
+    let mut set = ConfigSet::load(config_path, pwd)?;
+    let stale = set.stale_sections();
+    let removed = match remove {
+        true => set.remove_stale()?,
+        false => vec![],
+    };
+
+    let mut lines = vec![];
+    for (config, key, e) in &stale {
+        if removed.iter().any(|(c, k)| c == config && k == key) {
+            lines.push(format!("removed {:?} from {}", key, config.display()));
+        } else if remove {
+            lines.push(format!("kept {:?} in {}: {}", key, config.display(), e));
+        } else {
+            lines.push(format!("stale {:?} in {}: {}", key, config.display(), e));
+        }
+    }
+    if stale.is_empty() {
+        lines.push("no stale sections".to_string());
+    } else if !remove {
+        lines.push("run `dipse gc --remove` to remove the missing ones".to_string());
+    }
+    set.save_changed(removed.iter().map(|(c, _)| c.as_path()))?;
+    Ok(lines)
+}
+
+/// Point the sections for the directory `old` and below it to `new`, see [`ConfigSet::relocate`]
+///
+/// `old` and `new` are relative to `pwd`. With `dry_run` nothing is changed. Returns a line for
+/// every renamed section, or a note that there are none.
+/// Error:
+/// - A config file could not be locked, loaded or saved
+pub fn relocate_sections(
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    old: &Path,
+    new: &Path,
+    dry_run: bool,
+) -> Result<Vec<String>, Error> {
+    let mut locks = vec![];
+    if !dry_run {
+        lock_configs(&mut locks, config_path.as_deref(), pwd)?;
+    }
+    let mut set = ConfigSet::load(config_path, pwd)?;
+    let absolute = |p: &Path| match p.canonicalize() {
+        Ok(p) => p,
+        Err(_) => normalize(&pwd.join(p)),
+    };
+    let (old, new) = (absolute(old), absolute(new));
+
+    let renames = match dry_run {
+        true => set.relocations(&old, &new),
+        false => set.relocate(&old, &new)?,
+    };
+    let mut lines = renames
+        .iter()
+        .map(|(config, old_key, new_key)| {
+            format!(
+                "{}{}: {:?} -> {:?}",
+                if dry_run { "would rename " } else { "" },
+                config.display(),
+                old_key,
+                new_key
+            )
+        })
+        .collect::<Vec<_>>();
+    if renames.is_empty() {
+        lines.push(format!("no sections for {}", old.display()));
+    }
+    if !dry_run {
+        set.save_changed(renames.iter().map(|(c, ..)| c.as_path()))?;
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_dir;
+
+    /// `.d.toml` with `text` in a new directory for the test `name`, with a `sub` directory
+    fn project(name: &str, text: &str) -> (PathBuf, ConfigSet) {
+        let dir = test_dir(name);
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join(".d.toml"), text).unwrap();
+        let set = ConfigSet::from_path(dir.join(".d.toml")).unwrap();
+        (dir, set)
+    }
+
+    #[test]
+    fn add_target_takes_the_nearest_section() {
+        let (dir, mut set) = project("add-target-nearest", "[\".\"]\nb = \"make\"\n");
+        let target = set.add_target(&dir.join("sub"), false, false).unwrap();
+        assert_eq!(target, (dir.join(".d.toml"), PathBuf::from(".")));
+    }
+
+    #[test]
+    fn add_target_creates_the_exact_section() {
+        let (dir, mut set) = project("add-target-exact", "[\".\"]\nb = \"make\"\n");
+        match set.add_target(&dir.join("sub"), true, false) {
+            Err(Error::NoConfigForPath(p)) => assert_eq!(p, dir.join("sub")),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let target = set.add_target(&dir.join("sub"), true, true).unwrap();
+        assert_eq!(target, (dir.join(".d.toml"), PathBuf::from("sub")));
+        assert!(set.files[0].section(Path::new("sub")).is_some());
+        assert!(set.files[0].text.contains("[sub]"));
+    }
+
+    #[test]
+    fn add_target_creates_a_section_in_a_new_file() {
+        let dir = test_dir("add-target-new");
+        let path = dir.join(".d.toml");
+        let mut set = ConfigSet {
+            files: vec![Config {
+                path: path.clone(),
+                ..Config::default()
+            }],
+        };
+        let target = set.add_target(&dir, false, true).unwrap();
+        assert_eq!(target, (path, PathBuf::from(".")));
+    }
+
+    #[test]
+    fn transfer_copies_and_moves_between_files() {
+        let (dir, mut set) = project("transfer", "[\".\"]\nb = \"make\"\n");
+        let other = dir.join("other.toml");
+        fs::write(&other, format!("[{:?}]\n", dir.join("sub"))).unwrap();
+        set.files.push(Config::load(other.clone()).unwrap());
+        let (path, sub) = (dir.join(".d.toml"), dir.join("sub"));
+        let from = (path.as_path(), Path::new("."));
+        let b = set.files[0]
+            .section(Path::new("."))
+            .unwrap()
+            .get("b")
+            .cloned()
+            .unwrap();
+
+        set.transfer(&b, from, (&other, &sub), false, false)
+            .unwrap();
+        assert!(set.files[0]
+            .section(Path::new("."))
+            .unwrap()
+            .get("b")
+            .is_some());
+        assert_eq!(
+            set.files[1].section(&sub).unwrap().get("b").unwrap().cmd,
+            "make"
+        );
+
+        set.transfer(&b, from, (&path, Path::new("sub")), true, true)
+            .unwrap();
+        assert!(set.files[0]
+            .section(Path::new("."))
+            .unwrap()
+            .get("b")
+            .is_none());
+        assert!(set.files[0]
+            .section(Path::new("sub"))
+            .unwrap()
+            .get("b")
+            .is_some());
+        assert!(!set.files[0].text.contains("[\".\"]\nb"));
+    }
+
+    #[test]
+    fn transfer_keeps_the_source_when_the_target_fails() {
+        let (dir, mut set) = project(
+            "transfer-fails",
+            "[\".\"]\nb = \"make\"\n[sub]\nb = \"cargo build\"\n",
+        );
+        let path = dir.join(".d.toml");
+        let b = set.files[0]
+            .section(Path::new("."))
+            .unwrap()
+            .get("b")
+            .cloned()
+            .unwrap();
+        let (from, to) = (
+            (path.as_path(), Path::new(".")),
+            (path.as_path(), Path::new("sub")),
+        );
+
+        match set.transfer(&b, from, to, true, false) {
+            Err(Error::CmdStringExists(..)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(set.files[0]
+            .section(Path::new("."))
+            .unwrap()
+            .get("b")
+            .is_some());
+
+        let mut discovered = Alias::new("lint", "npm run lint");
+        discovered.discovered = true;
+        match set.transfer(&discovered, from, to, true, false) {
+            Err(Error::Discovered(name, _)) => assert_eq!(name, "lint"),
+            other => panic!("unexpected {:?}", other),
+        }
+        let copy = set.transfer(&discovered, from, to, false, false).unwrap();
+        assert!(!copy.discovered);
+    }
+
+    #[test]
+    fn remove_stale_only_removes_missing_directories() {
+        let text = "[\".\"]\n[sub]\n[gone]\nb = \"make\"\n[\"file/x\"]\n";
+        let (dir, mut set) = project("remove-stale", text);
+        fs::write(dir.join("file"), "").unwrap();
+
+        let stale = set.stale_sections();
+        let keys = stale.iter().map(|(_, k, _)| k.clone()).collect::<Vec<_>>();
+        assert_eq!(keys, [Path::new("gone"), Path::new("file/x")]);
+        assert_eq!(stale[0].2.kind(), ErrorKind::NotFound);
+        assert_ne!(stale[1].2.kind(), ErrorKind::NotFound);
+
+        let removed = set.remove_stale().unwrap();
+        assert_eq!(removed, [(dir.join(".d.toml"), PathBuf::from("gone"))]);
+        let keys = set.files[0]
+            .sections
+            .iter()
+            .map(|s| s.key.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [Path::new("."), Path::new("sub"), Path::new("file/x")]
+        );
+        assert!(!set.files[0].text.contains("gone"));
+    }
+
+    #[test]
+    fn relocate_rewrites_keys_below_the_old_directory() {
+        let (dir, _) = project("relocate", "");
+        let text = format!(
+            "[\".\"]\n[sub]\n[\"sub/deep\"]\n[subway]\n[{:?}]\n",
+            dir.join("sub/abs")
+        );
+        fs::write(dir.join(".d.toml"), text).unwrap();
+        let mut set = ConfigSet::from_path(dir.join(".d.toml")).unwrap();
+        let path = dir.join(".d.toml");
+
+        let renames = set.relocate(&dir.join("sub"), &dir.join("new")).unwrap();
+        assert_eq!(
+            renames,
+            [
+                (path.clone(), PathBuf::from("sub"), PathBuf::from("new")),
+                (
+                    path.clone(),
+                    PathBuf::from("sub/deep"),
+                    PathBuf::from("new/deep")
+                ),
+                (path.clone(), dir.join("sub/abs"), dir.join("new/abs")),
+            ]
+        );
+        let keys = set.files[0]
+            .sections
+            .iter()
+            .map(|s| s.key.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                PathBuf::from("."),
+                PathBuf::from("new"),
+                PathBuf::from("new/deep"),
+                PathBuf::from("subway"),
+                dir.join("new/abs"),
+            ]
+        );
+
+        // Outside of the directory of the file, relative keys become absolute
+        let elsewhere = Path::new("/elsewhere/new");
+        let renames = set.relocations(&dir.join("new/deep"), elsewhere);
+        assert_eq!(
+            renames,
+            [(path, PathBuf::from("new/deep"), elsewhere.to_path_buf())]
+        );
+    }
+
+    #[test]
+    fn lock_waits_for_the_holder() {
+        let path = test_dir("lock").join(".d.toml");
+        let lock = Lock::acquire(&path).unwrap();
+        match Lock::acquire_within(&path, Duration::from_millis(100)) {
+            Err(Error::ConfigFileWrite(p, e)) => {
+                assert_eq!(p, path);
+                assert_eq!(e.kind(), ErrorKind::WouldBlock);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        drop(lock);
+        Lock::acquire_within(&path, Duration::from_millis(100)).unwrap();
+        // The lock file is kept out of the project
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn backup_keeps_the_last_versions() {
+        let dir = test_dir("backup");
+        let path = dir.join(".d.toml");
+        backup(&path).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        for n in 0..5 {
+            fs::write(&path, n.to_string()).unwrap();
+            backup(&path).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read(".d.toml.bak"), "4");
+        assert_eq!(read(".d.toml.bak.1"), "3");
+        assert_eq!(read(".d.toml.bak.2"), "2");
+        assert!(!dir.join(format!(".d.toml.bak.{}", BACKUPS)).exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn write_atomic_replaces_the_target_of_a_link() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = test_dir("write-atomic");
+        let (target, link) = (dir.join("d.toml"), dir.join(".d.toml"));
+        write_atomic(&target, "old").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+        symlink(&target, &link).unwrap();
+        write_atomic(&link, "new").unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        // No temporary file is left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,522 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Aliases found in the files of a project's native tools.
+//!
+//! A section opts in with `discover = true` for every tool, or with a list like
+//! `discover = ["npm", "make"]`. Aliases written in the config always win over discovered ones
+//! of the same section.
+
+use crate::{
+    export::shell_quote,
+    model::{Alias, Source},
+};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// A tool whose tasks can become aliases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tool {
+    /// `scripts` of `package.json`, run with `npm run`
+    Npm,
+    /// Targets of a Makefile, run with `make`
+    Make,
+    /// Recipes of a justfile, run with `just`
+    Just,
+    /// `[alias]` of `.cargo/config.toml`, run with `cargo`
+    Cargo,
+}
+
+impl Tool {
+    /// Every tool, in the order their aliases are looked at
+    pub const ALL: [Tool; 4] = [Tool::Npm, Tool::Make, Tool::Just, Tool::Cargo];
+
+    /// Files the tool reads its tasks from, the first one that exists is used
+    pub fn files(self) -> &'static [&'static str] {
+        match self {
+            Tool::Npm => &["package.json"],
+            Tool::Make => &["GNUmakefile", "makefile", "Makefile"],
+            Tool::Just => &["justfile", "Justfile", ".justfile"],
+            Tool::Cargo => &[".cargo/config.toml", ".cargo/config"],
+        }
+    }
+
+    /// Command that runs the task `name`, the name is quoted for the shell
+    pub fn command(self, name: &str) -> String {
+        let name = shell_quote(name);
+        match self {
+            Tool::Npm => format!("npm run {}", name),
+            Tool::Make => format!("make {}", name),
+            Tool::Just => format!("just {}", name),
+            Tool::Cargo => format!("cargo {}", name),
+        }
+    }
+
+    /// Task names, descriptions and lines in the text of a tool file
+    /// Error:
+    /// - The file could not be parsed
+    pub fn tasks(self, text: &str) -> Result<Vec<Task>, String> {
+        match self {
+            Tool::Npm => npm_scripts(text),
+            Tool::Make => Ok(make_targets(text)),
+            Tool::Just => Ok(just_recipes(text)),
+            Tool::Cargo => cargo_aliases(text),
+        }
+    }
+}
+
+impl fmt::Display for Tool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Tool::Npm => "npm",
+            Tool::Make => "make",
+            Tool::Just => "just",
+            Tool::Cargo => "cargo",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Tool {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "npm" => Ok(Tool::Npm),
+            "make" => Ok(Tool::Make),
+            "just" => Ok(Tool::Just),
+            "cargo" => Ok(Tool::Cargo),
+            _ => Err(format!(
+                "unknown tool {:?}, expected one of npm, make, just, cargo",
+                s
+            )),
+        }
+    }
+}
+
+/// A task defined in a tool file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    /// Name of the task
+    pub name: String,
+    /// What the task does, if the file says so
+    pub description: Option<String>,
+    /// 1-based line the task is defined at
+    pub line: usize,
+}
+
+/// Aliases for the tasks of `tools` in `dir`
+///
+/// Missing files are skipped. Files that can not be read or parsed are returned as errors next
+/// to the aliases of the other tools.
+pub fn discover(dir: &Path, tools: &[Tool]) -> (Vec<Alias>, Vec<(Source, String)>) {
+    let mut aliases: Vec<Alias> = vec![];
+    let mut errors = vec![];
+
+    for tool in tools {
+        let path = match tool
+            .files()
+            .iter()
+            .map(|f| dir.join(f))
+            .find(|p| p.is_file())
+        {
+            Some(p) => p,
+            None => continue,
+        };
+
+        let tasks = match fs::read_to_string(&path) {
+            Err(e) => Err(e.to_string()),
+            Ok(text) => tool.tasks(&text),
+        };
+        let tasks = match tasks {
+            Err(e) => {
+                errors.push((Source::new(&path, 0), e));
+                continue;
+            }
+            Ok(t) => t,
+        };
+
+        for task in tasks {
+            if aliases.iter().any(|a| a.name == task.name) {
+                continue;
+            }
+            aliases.push(Alias {
+                description: task.description,
+                source: Source::new(&path, task.line),
+                discovered: true,
+                ..Alias::new(&task.name, &tool.command(&task.name))
+            });
+        }
+    }
+
+    (aliases, errors)
+}
+
+/// Aliases for the executables in `dir`, run directly with the arguments as argv
+///
+/// The first comment of a script, after the shebang, is its description. A missing directory
+/// gives no aliases.
+pub fn scripts(dir: &Path) -> (Vec<Alias>, Vec<(Source, String)>) {
+    let mut aliases: Vec<Alias> = vec![];
+    let mut errors = vec![];
+
+    let entries = match fs::read_dir(dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return (aliases, errors),
+        Err(e) => {
+            errors.push((Source::new(dir, 0), e.to_string()));
+            return (aliases, errors);
+        }
+        Ok(e) => e,
+    };
+
+    let mut files = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| is_executable(p))
+        .collect::<Vec<PathBuf>>();
+    files.sort();
+
+    for file in files {
+        let name = match file.file_stem().and_then(|s| s.to_str()) {
+            Some(n) if !n.starts_with('.') => n.to_string(),
+            _ => continue,
+        };
+        if aliases.iter().any(|a| a.name == name) {
+            continue;
+        }
+        let (description, line) = match fs::read(&file) {
+            Ok(bytes) => header_comment(&String::from_utf8_lossy(&bytes)),
+            Err(_) => (None, 0),
+        };
+        aliases.push(Alias {
+            description,
+            source: Source::new(&file, line),
+            discovered: true,
+            file: Some(file.clone()),
+            ..Alias::new(&name, &file.display().to_string())
+        });
+    }
+
+    (aliases, errors)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    match fs::metadata(path) {
+        Ok(m) => m.is_file() && m.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// First comment of a script after the shebang and the line it is on
+fn header_comment(text: &str) -> (Option<String>, usize) {
+    for (idx, line) in text.lines().enumerate().take(20) {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("#!") {
+            continue;
+        }
+        let comment = match line
+            .strip_prefix("//")
+            .or_else(|| line.strip_prefix("--"))
+            .or_else(|| line.strip_prefix('#'))
+        {
+            Some(c) => c.trim(),
+            None => break,
+        };
+        if !comment.is_empty() {
+            return (Some(comment.to_string()), idx + 1);
+        }
+    }
+    (None, 0)
+}
+
+/// 1-based line of the first line containing `needle` at or after line `from`
+fn line_of(text: &str, needle: &str, from: usize) -> usize {
+    text.lines()
+        .enumerate()
+        .skip(from.saturating_sub(1))
+        .find(|(_, l)| l.contains(needle))
+        .map(|(i, _)| i + 1)
+        .unwrap_or(0)
+}
+
+/// 1-based line of the first `key = ...` at or after line `from`
+fn key_line(text: &str, key: &str, from: usize) -> usize {
+    text.lines()
+        .enumerate()
+        .skip(from.saturating_sub(1))
+        .find(|(_, l)| {
+            let l = l.trim_start().trim_start_matches(['"', '\'']);
+            l.strip_prefix(key)
+                .map(|rest| {
+                    rest.trim_start_matches(['"', '\''])
+                        .trim_start()
+                        .starts_with('=')
+                })
+                .unwrap_or(false)
+        })
+        .map(|(i, _)| i + 1)
+        .unwrap_or(0)
+}
+
+/// `scripts` of a `package.json`, the script itself is the description
+fn npm_scripts(text: &str) -> Result<Vec<Task>, String> {
+    let package = match serde_json::from_str::<serde_json::Value>(text) {
+        Err(e) => return Err(e.to_string()),
+        Ok(p) => p,
+    };
+    let scripts = match package.get("scripts").and_then(|s| s.as_object()) {
+        Some(s) => s,
+        None => return Ok(vec![]),
+    };
+
+    let scripts_line = line_of(text, "\"scripts\"", 0);
+    Ok(scripts
+        .iter()
+        .map(|(name, script)| Task {
+            name: name.clone(),
+            description: script.as_str().map(|s| s.to_string()),
+            line: line_of(text, &format!("\"{}\"", name), scripts_line),
+        })
+        .collect())
+}
+
+/// Targets of a Makefile
+///
+/// Special targets (`.PHONY`), pattern rules and variables are skipped. A `## comment` after
+/// the target is its description.
+fn make_targets(text: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = vec![];
+
+    for (idx, line) in text.lines().enumerate() {
+        if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '.') {
+            continue;
+        }
+        let (targets, rest) = match line.split_once(':') {
+            Some(t) => t,
+            None => continue,
+        };
+        // `a := b` and `a ::= b` are variables
+        if rest.starts_with('=') || rest.starts_with(":=") || targets.contains('=') {
+            continue;
+        }
+        let description = rest
+            .split_once("##")
+            .map(|(_, d)| d.trim().to_string())
+            .filter(|d| !d.is_empty());
+
+        for target in targets.split_whitespace() {
+            if target.contains(['%', '$', '/']) {
+                continue;
+            }
+            if tasks.iter().any(|t| t.name == target) {
+                continue;
+            }
+            tasks.push(Task {
+                name: target.to_string(),
+                description: description.clone(),
+                line: idx + 1,
+            });
+        }
+    }
+
+    tasks
+}
+
+/// Recipes of a justfile, a comment right above a recipe is its description
+fn just_recipes(text: &str) -> Vec<Task> {
+    let mut tasks = vec![];
+    let mut comment: Option<String> = None;
+
+    for (idx, line) in text.lines().enumerate() {
+        if let Some(c) = line.strip_prefix('#') {
+            if !c.starts_with('!') {
+                comment = Some(c.trim().to_string());
+            }
+            continue;
+        }
+        let description = comment.take();
+
+        if line.is_empty() || line.starts_with(char::is_whitespace) || line.starts_with('[') {
+            continue;
+        }
+        let (head, _) = match line.split_once(':') {
+            Some(h) => h,
+            None => continue,
+        };
+        // `a := b` is a variable
+        if line[head.len()..].starts_with(":=") {
+            continue;
+        }
+        let mut words = head.split_whitespace();
+        let name = match words.next() {
+            Some(n) => n.trim_start_matches('@'),
+            None => continue,
+        };
+        if ["set", "alias", "export", "import", "mod"].contains(&name) || name.starts_with('_') {
+            continue;
+        }
+
+        tasks.push(Task {
+            name: name.to_string(),
+            description,
+            line: idx + 1,
+        });
+    }
+
+    tasks
+}
+
+/// `[alias]` of a cargo config, the expansion is the description
+fn cargo_aliases(text: &str) -> Result<Vec<Task>, String> {
+    let config = match toml::from_str::<toml::Value>(text) {
+        Err(e) => return Err(e.to_string()),
+        Ok(c) => c,
+    };
+    let aliases = match config.get("alias").and_then(|a| a.as_table()) {
+        Some(a) => a,
+        None => return Ok(vec![]),
+    };
+
+    let alias_line = line_of(text, "[alias]", 0);
+    Ok(aliases
+        .iter()
+        .map(|(name, expansion)| {
+            let description = match expansion {
+                toml::Value::String(s) => Some(s.clone()),
+                toml::Value::Array(a) => Some(
+                    a.iter()
+                        .filter_map(|v| v.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                _ => None,
+            };
+            Task {
+                name: name.clone(),
+                description,
+                line: key_line(text, name, alias_line),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(name: &str, description: Option<&str>, line: usize) -> Task {
+        Task {
+            name: name.to_string(),
+            description: description.map(|d| d.to_string()),
+            line,
+        }
+    }
+
+    #[test]
+    fn make_targets_skip_special_targets_patterns_and_variables() {
+        let text = "CC := gcc\n\
+                    FLAGS = -O2\n\
+                    .PHONY: build test\n\
+                    \n\
+                    build: main.o ## Build everything\n\
+                    \tgcc -o main main.o\n\
+                    %.o: %.c\n\
+                    \t$(CC) -c $<\n\
+                    test lint: build\n\
+                    build:\n\
+                    out/bin: build\n";
+
+        assert_eq!(
+            make_targets(text),
+            [
+                task("build", Some("Build everything"), 5),
+                task("test", None, 9),
+                task("lint", None, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn just_recipes_take_the_comment_above_as_description() {
+        let text = "set shell := [\"bash\", \"-c\"]\n\
+                    version := \"1.0\"\n\
+                    \n\
+                    # Build the project\n\
+                    build *args:\n\
+                    \tcargo build {{args}}\n\
+                    \n\
+                    [private]\n\
+                    @test: build\n\
+                    \tcargo test\n\
+                    _helper:\n\
+                    \techo\n\
+                    alias b := build\n";
+
+        assert_eq!(
+            just_recipes(text),
+            [
+                task("build", Some("Build the project"), 5),
+                task("test", None, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn just_recipes_ignore_shebang_lines() {
+        let text = "py:\n\
+                    #!/usr/bin/env python3\n\
+                    \tprint(1)\n";
+
+        assert_eq!(just_recipes(text), [task("py", None, 1)]);
+    }
+
+    #[test]
+    fn npm_scripts_are_described_by_the_script() {
+        let text = "{\n  \"name\": \"app\",\n  \"scripts\": {\n    \"lint\": \"eslint .\",\n    \"test\": \"jest\"\n  }\n}\n";
+
+        assert_eq!(
+            npm_scripts(text).unwrap(),
+            [
+                task("lint", Some("eslint ."), 4),
+                task("test", Some("jest"), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn npm_scripts_of_a_package_without_scripts() {
+        assert_eq!(npm_scripts("{\"name\": \"app\"}").unwrap(), []);
+        assert!(npm_scripts("{").is_err());
+    }
+
+    #[test]
+    fn commands_quote_task_names() {
+        assert_eq!(Tool::Npm.command("lint"), "npm run lint");
+        assert_eq!(Tool::Make.command("a;b"), "make 'a;b'");
+    }
+}
@@ -0,0 +1,308 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Format preserving changes to config files
+//!
+//! The model in [`crate::model`] is what `dipse` works with, but writing it back with serde
+//! loses comments, blank lines and the order of the file. These functions apply the same
+//! changes to a [`DocumentMut`] of the original text instead.
+
+use crate::model::{Alias, Confirm};
+use toml_edit::{value, DocumentMut, Formatted, InlineTable, Item, Key, Table, TableLike, Value};
+
+/// Table of the section `key`
+fn section<'a>(doc: &'a mut DocumentMut, key: &str) -> Result<&'a mut dyn TableLike, String> {
+    match doc.get_mut(key).and_then(|i| i.as_table_like_mut()) {
+        Some(t) => Ok(t),
+        None => Err(format!("no section {:?}", key)),
+    }
+}
+
+/// Add an empty section for `key` at the end of the document, if there is none
+pub fn add_section(doc: &mut DocumentMut, key: &str) {
+    if doc.get(key).is_none() {
+        doc.insert(key, Item::Table(Table::new()));
+    }
+}
+
+/// Alias as an item, a plain string when possible
+///
+/// Scripts become a sub table so they can be written as multi-line strings, other tables are
+/// written inline.
+fn alias_item(alias: &Alias) -> Item {
+    if alias.is_plain() {
+        return value(&alias.cmd);
+    }
+
+    let mut table = InlineTable::new();
+    fill(&mut table, alias);
+    if alias.script.is_some() {
+        return Item::Table(table.into_table());
+    }
+    Item::Value(Value::InlineTable(table))
+}
+
+/// Set the keys of an alias table, keeping other formatting
+fn fill(table: &mut dyn TableLike, alias: &Alias) {
+    let mut set = |name: &str, v: Option<Value>| match v {
+        Some(v) => match table.get_mut(name) {
+            Some(Item::Value(old)) => {
+                let decor = old.decor().clone();
+                *old = v;
+                *old.decor_mut() = decor;
+            }
+            _ => {
+                table.insert(name, Item::Value(v));
+            }
+        },
+        None => {
+            table.remove(name);
+        }
+    };
+
+    match &alias.script {
+        Some(script) => {
+            set("cmd", None);
+            set("script", Some(Value::from(script.as_str())));
+        }
+        None => {
+            set("cmd", Some(Value::from(alias.cmd.as_str())));
+            set("script", None);
+        }
+    }
+    set("interpreter", alias.interpreter.as_deref().map(Value::from));
+    set("description", alias.description.as_deref().map(Value::from));
+    let env = match alias.env.is_empty() {
+        true => None,
+        false => {
+            let mut env = InlineTable::new();
+            for (k, v) in &alias.env {
+                env.insert(k, Value::from(v.as_str()));
+            }
+            Some(Value::InlineTable(env))
+        }
+    };
+    set("env", env);
+    let confirm = match &alias.confirm {
+        Some(Confirm::Ask(b)) => Some(Value::from(*b)),
+        Some(Confirm::Message(m)) => Some(Value::from(m.as_str())),
+        None => None,
+    };
+    set("confirm", confirm);
+}
+
+/// Add `alias` to the section `key`, or change the alias with the same name in place
+/// Error:
+/// - Section does not exist
+pub fn set_alias(doc: &mut DocumentMut, key: &str, alias: &Alias) -> Result<(), String> {
+    let section = section(doc, key)?;
+    let plain = alias.is_plain();
+
+    // Changing between a value and a sub table needs a new key, a header key is formatted
+    // differently
+    let replace = match section.get_mut(&alias.name) {
+        None => true,
+        Some(Item::Value(Value::String(s))) if plain => {
+            let decor = s.decor().clone();
+            *s = Formatted::new(alias.cmd.clone());
+            *s.decor_mut() = decor;
+            false
+        }
+        Some(Item::Value(Value::InlineTable(t))) if !plain && alias.script.is_none() => {
+            fill(t, alias);
+            false
+        }
+        Some(Item::Table(t)) if alias.script.is_some() => {
+            fill(t, alias);
+            false
+        }
+        Some(item @ Item::Value(_)) if alias.script.is_none() => {
+            let decor = item
+                .as_value()
+                .map(|v| v.decor().clone())
+                .unwrap_or_default();
+            *item = alias_item(alias);
+            if let Some(v) = item.as_value_mut() {
+                *v.decor_mut() = decor;
+            }
+            false
+        }
+        Some(_) => true,
+    };
+    if replace {
+        section.remove(&alias.name);
+        section.insert(&alias.name, alias_item(alias));
+    }
+    Ok(())
+}
+
+/// Remove the alias `name` from the section `key`
+/// Error:
+/// - Section does not exist
+pub fn remove_alias(doc: &mut DocumentMut, key: &str, name: &str) -> Result<(), String> {
+    section(doc, key)?.remove(name);
+    Ok(())
+}
+
+/// `k`, or `new` with the formatting of `k` if it is called `old`
+fn renamed(k: &Key, old: &str, new: &str) -> Key {
+    if k.get() != old {
+        return k.clone();
+    }
+    let mut renamed = Key::new(new);
+    *renamed.leaf_decor_mut() = k.leaf_decor().clone();
+    renamed
+}
+
+/// Rename the key `old` of `table` to `new` without moving it
+fn rename_key(table: &mut Table, old: &str, new: &str) {
+    let entries = table
+        .iter()
+        .filter_map(|(k, _)| table.get_key_value(k))
+        .map(|(k, i)| (renamed(k, old, new), i.clone()))
+        .collect::<Vec<_>>();
+    table.clear();
+    for (k, i) in entries {
+        table.insert_formatted(&k, i);
+    }
+}
+
+/// Rename the alias `old` of the section `key` to `new`, keeping its place and comments
+/// Error:
+/// - Section does not exist
+pub fn rename_alias(doc: &mut DocumentMut, key: &str, old: &str, new: &str) -> Result<(), String> {
+    match doc.get_mut(key) {
+        Some(Item::Table(table)) => {
+            rename_key(table, old, new);
+            Ok(())
+        }
+        Some(Item::Value(Value::InlineTable(table))) => {
+            let entries = table
+                .iter()
+                .filter_map(|(k, _)| table.get_key_value(k))
+                .filter_map(|(k, i)| Some((renamed(k, old, new), i.as_value()?.clone())))
+                .collect::<Vec<_>>();
+            table.clear();
+            for (k, v) in entries {
+                table.insert_formatted(&k, v);
+            }
+            Ok(())
+        }
+        _ => Err(format!("no section {:?}", key)),
+    }
+}
+
+/// Give the section `old` the key `new`, keeping its place and comments
+pub fn rename_section(doc: &mut DocumentMut, old: &str, new: &str) {
+    rename_key(doc.as_table_mut(), old, new);
+}
+
+/// Remove the section `key` with all its aliases
+pub fn remove_section(doc: &mut DocumentMut, key: &str) {
+    doc.remove(key);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "# Projects\n\
+                        [\"/a\"]\n\
+                        # build it\n\
+                        b = \"make\"    # fast\n\
+                        t = { cmd = \"make test\", description = \"Tests\" }\n\
+                        \n\
+                        [\"/b\"]\n\
+                        r = \"cargo run\"\n";
+
+    fn doc() -> DocumentMut {
+        TEXT.parse().unwrap()
+    }
+
+    #[test]
+    fn set_alias_changes_a_command_in_place() {
+        let mut doc = doc();
+        set_alias(&mut doc, "/a", &Alias::new("b", "make -j4")).unwrap();
+
+        assert_eq!(
+            doc.to_string(),
+            TEXT.replace("b = \"make\"", "b = \"make -j4\"")
+        );
+    }
+
+    #[test]
+    fn set_alias_keeps_other_keys_of_a_table() {
+        let mut doc = doc();
+        let alias = Alias {
+            description: Some("Tests".to_string()),
+            ..Alias::new("t", "cargo test")
+        };
+        set_alias(&mut doc, "/a", &alias).unwrap();
+
+        assert_eq!(doc.to_string(), TEXT.replace("make test", "cargo test"));
+    }
+
+    #[test]
+    fn set_alias_appends_new_aliases_to_the_section() {
+        let mut doc = doc();
+        set_alias(&mut doc, "/b", &Alias::new("c", "cargo check")).unwrap();
+
+        assert_eq!(doc.to_string(), format!("{}c = \"cargo check\"\n", TEXT));
+        assert!(set_alias(&mut doc, "/c", &Alias::new("c", "x")).is_err());
+    }
+
+    #[test]
+    fn remove_alias_leaves_the_rest() {
+        let mut doc = doc();
+        remove_alias(&mut doc, "/a", "t").unwrap();
+
+        assert_eq!(
+            doc.to_string(),
+            TEXT.replace("t = { cmd = \"make test\", description = \"Tests\" }\n", "")
+        );
+    }
+
+    #[test]
+    fn rename_alias_keeps_place_and_comments() {
+        let mut doc = doc();
+        rename_alias(&mut doc, "/a", "b", "build").unwrap();
+
+        assert_eq!(
+            doc.to_string(),
+            TEXT.replace("b = \"make\"", "build = \"make\"")
+        );
+    }
+
+    #[test]
+    fn rename_section_keeps_its_place() {
+        let mut doc = doc();
+        rename_section(&mut doc, "/a", "/c");
+
+        assert_eq!(doc.to_string(), TEXT.replace("[\"/a\"]", "[\"/c\"]"));
+    }
+
+    #[test]
+    fn add_section_once() {
+        let mut doc = doc();
+        add_section(&mut doc, "/c");
+        add_section(&mut doc, "/c");
+        add_section(&mut doc, "/a");
+
+        assert_eq!(doc.to_string(), format!("{}\n[\"/c\"]\n", TEXT));
+    }
+}
@@ -0,0 +1,231 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `dipse edit`
+//!
+//! The editor is taken from `VISUAL`, then `EDITOR`, then `vi`. It is started directly, not
+//! through a shell, so paths with spaces work. After the editor exits the file is parsed
+//! again. If it is broken, the user can open it again at the error, restore the previous
+//! version or keep it anyway, like `visudo` does.
+
+use crate::{
+    config::{write_atomic, ConfigSet},
+    error::Error,
+    model::Config,
+    picker::split_args,
+    resolver::Resolver,
+    trust,
+};
+use dialoguer::Select;
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Editor used when neither `VISUAL` nor `EDITOR` is set
+const FALLBACK: &str = "vi";
+
+/// Editors that open a file at a line with `+<line> <file>`
+const PLUS_LINE: &[&str] = &[
+    "vi",
+    "vim",
+    "nvim",
+    "gvim",
+    "view",
+    "nano",
+    "pico",
+    "emacs",
+    "emacsclient",
+    "micro",
+    "kak",
+    "joe",
+    "ne",
+    "mg",
+];
+
+/// Editors that open a file at a line with `<file>:<line>`
+const COLON_LINE: &[&str] = &["subl", "hx", "helix", "zed"];
+
+/// Editors that open a file at a line with `-g <file>:<line>`
+const GOTO_LINE: &[&str] = &["code", "code-insiders", "codium"];
+
+/// Program and arguments of the editor
+pub fn editor() -> Vec<String> {
+    for var in ["VISUAL", "EDITOR"] {
+        if let Some(words) = env::var(var).ok().map(|e| split_args(&e)) {
+            if !words.is_empty() {
+                return words;
+            }
+        }
+    }
+    vec![FALLBACK.to_string()]
+}
+
+/// Command opening `path` at `line` (1-based) with `editor`
+fn command(editor: &[String], path: &Path, line: Option<usize>) -> Command {
+    let mut cmd = Command::new(&editor[0]);
+    cmd.args(&editor[1..]);
+
+    let name = Path::new(&editor[0])
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match line.filter(|l| *l > 0) {
+        Some(l) if PLUS_LINE.contains(&name.as_str()) => {
+            cmd.arg(format!("+{}", l)).arg(path);
+        }
+        Some(l) if COLON_LINE.contains(&name.as_str()) => {
+            cmd.arg(format!("{}:{}", path.display(), l));
+        }
+        Some(l) if GOTO_LINE.contains(&name.as_str()) => {
+            cmd.arg("-g").arg(format!("{}:{}", path.display(), l));
+        }
+        _ => {
+            cmd.arg(path);
+        }
+    }
+    cmd
+}
+
+/// Line (1-based) of a parse error
+pub fn error_line(e: &Error) -> Option<usize> {
+    match e {
+        Error::UnableToParse(_, e) => e.line_col().map(|(line, _)| line + 1),
+        _ => None,
+    }
+}
+
+/// Config file to edit for `pwd` and the line to open it at
+///
+/// A file that does not parse is opened where it is broken, otherwise the file of the section
+/// that applies to `pwd` at that section, or the first file.
+/// Error:
+/// - There is no config file
+pub fn target(config_path: Option<PathBuf>, pwd: &Path) -> Result<(PathBuf, Option<usize>), Error> {
+    let paths = match config_path {
+        Some(c) => vec![c],
+        None => ConfigSet::discover_paths(pwd)?,
+    };
+    if paths.is_empty() {
+        return Err(Error::NoConfigFile);
+    }
+
+    let mut files = vec![];
+    for path in paths {
+        match Config::load(path.clone()) {
+            Err(e) => return Ok((path, error_line(&e))),
+            Ok(config) => files.push(config),
+        }
+    }
+    let set = ConfigSet { files };
+    let resolution = Resolver::new(&set).resolve(pwd)?;
+    match resolution.best() {
+        Ok(c) => Ok((c.config.path.clone(), Some(c.section.source.line))),
+        Err(_) => Ok((set.files[0].path.clone(), None)),
+    }
+}
+
+/// What to do with a config file that does not parse after editing
+enum Choice {
+    Edit,
+    Restore,
+    Keep,
+}
+
+/// Ask what to do with the broken file
+fn ask() -> Result<Choice, Error> {
+    let choice = Select::new()
+        .with_prompt("What now?")
+        .items(&[
+            "Edit the file again",
+            "Restore the previous version",
+            "Keep the broken file",
+        ])
+        .default(0)
+        .interact_opt();
+    match choice {
+        Err(dialoguer::Error::IO(e)) => Err(Error::Prompt(e)),
+        Ok(Some(0)) => Ok(Choice::Edit),
+        Ok(Some(1)) => Ok(Choice::Restore),
+        Ok(_) => Ok(Choice::Keep),
+    }
+}
+
+/// Open the config file at `path` in the editor, at `line` if given, and check it afterwards
+///
+/// Messages go to `out`. A file that was allowed to run aliases stays allowed with the new
+/// contents, the user wrote them.
+/// Error:
+/// - The editor could not be started
+/// - The file could not be read or restored
+/// - The file does not parse and was kept that way
+pub fn edit(out: &mut dyn Write, path: &Path, mut line: Option<usize>) -> Result<(), Error> {
+    let previous = match fs::read_to_string(path) {
+        Ok(t) => Some(t),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(Error::NoFile(path.to_path_buf(), e)),
+    };
+    let allowed = previous
+        .as_deref()
+        .is_none_or(|t| trust::is_allowed(path, t));
+    let editor = editor();
+
+    loop {
+        let status = match command(&editor, path, line).status() {
+            Err(e) => return Err(Error::Command(e)),
+            Ok(s) => s,
+        };
+        if !status.success() {
+            let _ = writeln!(out, "{} exited with {}", editor[0], status);
+        }
+
+        let e = match Config::load(path.to_path_buf()) {
+            Ok(config) if allowed => return trust::allow(path, &config.text),
+            Ok(_) => return Ok(()),
+            Err(e @ Error::UnableToParse(..)) => e,
+            // Removed or unreadable, nothing to check
+            Err(_) => return Ok(()),
+        };
+        // Nobody to ask, keep the file and report the error
+        if !(io::stdin().is_terminal() && io::stderr().is_terminal()) {
+            return Err(e);
+        }
+        let cause = std::error::Error::source(&e)
+            .map(|c| c.to_string())
+            .unwrap_or_default();
+        let _ = writeln!(out, "{}\n{}", e, cause);
+
+        match ask()? {
+            Choice::Edit => line = error_line(&e).or(line),
+            Choice::Keep => return Err(e),
+            Choice::Restore => {
+                let res = match &previous {
+                    Some(text) => write_atomic(path, text),
+                    None => fs::remove_file(path),
+                };
+                if let Err(e) = res {
+                    return Err(Error::ConfigFileWrite(path.to_path_buf(), e));
+                }
+                let _ = writeln!(out, "restored the previous version of {}", path.display());
+                return Ok(());
+            }
+        }
+    }
+}
@@ -16,26 +16,61 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{fmt, io, path::PathBuf};
+use crate::model::Source;
+use std::{error, fmt, io, path::PathBuf};
+
+/// Exit code for errors without a more specific code
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code when no config file or no section for the current directory was found
+pub const EXIT_CONFIG_NOT_FOUND: i32 = 78;
+/// Exit code when a config file could not be parsed or serialized
+pub const EXIT_PARSE: i32 = 65;
+/// Exit code when an alias is not defined
+///
+/// Not 127, which `sh` returns when the command of an alias is not found.
+pub const EXIT_ALIAS_MISSING: i32 = 64;
+/// Exit code when reading, writing or spawning failed
+pub const EXIT_IO: i32 = 74;
+/// Exit code when a config file is not allowed to run aliases
+pub const EXIT_NOPERM: i32 = 77;
 
 /// Error enum with all possible error cases
+#[derive(Debug)]
 pub enum Error {
     /// Configuration alr exists for init cmd
     ConfigExist(PathBuf),
     /// No file found at specified path
     NoFile(PathBuf, io::Error),
     /// TOML Parsing error
-    UnableToParse(toml::de::Error),
+    UnableToParse(PathBuf, toml::de::Error),
     /// TOML Serialization error
     UnableToSerialize(toml::ser::Error),
-    /// Unable to find the command string in Entry map
-    NoCmdStringFound(PathBuf, String),
+    /// Config file could not be changed in place
+    UnableToEdit(PathBuf, String),
+    /// File to import aliases from is not valid
+    UnableToImport(PathBuf, String),
+    /// Unable to find the alias in the config sections, with similar alias names
+    NoCmdStringFound(PathBuf, String, Vec<String>),
     /// New command to be inserted already exists
     CmdStringExists(PathBuf, String),
+    /// Config file already has a section with the key
+    SectionExists(PathBuf, PathBuf),
+    /// Alias name is used for a section setting
+    ReservedName(String),
+    /// Alias is discovered from a tool file, not written in a config file
+    Discovered(String, Source),
+    /// Alias is defined by a less specific section than the one of the directory
+    OtherSection(PathBuf, PathBuf, String),
     /// No configuration file found, even after traversing upwards
     NoConfigForPath(PathBuf),
     /// Error running a shell command
     Command(io::Error),
+    /// Command of an alias exited with a non zero code
+    ChildFailed(String, i32),
+    /// Error while writing the output of a subcommand
+    Output(io::Error),
+    /// Error while asking the user in the terminal
+    Prompt(io::Error),
     /// Unable to get CWD
     CurrentDir,
     /// Unable to get the configuration directory
@@ -50,12 +85,102 @@ pub enum Error {
     ConfigDirCreation(PathBuf, io::Error),
     /// Created a new configuration, time for the user to update it.
     NewConfig(PathBuf),
+    /// Unable to get the data directory the history is kept in
+    DataDir,
+    /// Error while reading or writing the history
+    History(PathBuf, io::Error),
+    /// Alias was changed again after the change to undo
+    AliasChanged(PathBuf, String),
+    /// No change in the history to undo
+    NothingToUndo,
+    /// Error while reading or writing the allowlist
+    Allowlist(PathBuf, io::Error),
+    /// The user did not confirm running a command
+    Declined(String),
+    /// A command needs confirmation, but there is no terminal to ask in
+    NotConfirmed(String),
+    /// Config file is not allowed to run aliases, whether it changed since it was allowed and
+    /// the diff to the allowed version
+    NotAllowed(PathBuf, bool, String),
 
     /// When config file is not found
     NoConfigFile,
 }
 
+impl Error {
+    /// Exit code for the `dipse` binary
+    ///
+    /// | Code | Meaning                                             |
+    /// |------|-----------------------------------------------------|
+    /// | 1    | Any other error                                     |
+    /// | 64   | The alias is not defined                            |
+    /// | 65   | A config file could not be parsed or serialized     |
+    /// | 74   | Reading, writing or spawning failed                 |
+    /// | 77   | The config file is not allowed to run aliases       |
+    /// | 78   | No config file or no section for the directory      |
+    /// | n    | The command of an alias failed with exit code `n`   |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoConfigFile | Error::NoConfigForPath(_) => EXIT_CONFIG_NOT_FOUND,
+            Error::UnableToParse(..)
+            | Error::UnableToSerialize(_)
+            | Error::UnableToEdit(..)
+            | Error::UnableToImport(..) => EXIT_PARSE,
+            Error::NoCmdStringFound(..) => EXIT_ALIAS_MISSING,
+            Error::ChildFailed(_, code) => *code,
+            Error::NotAllowed(..) => EXIT_NOPERM,
+            Error::NoFile(..)
+            | Error::Command(_)
+            | Error::Output(_)
+            | Error::Prompt(_)
+            | Error::CurrentDir
+            | Error::ConfigDir
+            | Error::DataDir
+            | Error::History(..)
+            | Error::Allowlist(..)
+            | Error::ConfigPath(_)
+            | Error::ConfigFileCreation(..)
+            | Error::ConfigFileWrite(..)
+            | Error::ConfigDirCreation(..) => EXIT_IO,
+            Error::ConfigExist(_)
+            | Error::CmdStringExists(..)
+            | Error::ReservedName(_)
+            | Error::SectionExists(..)
+            | Error::Discovered(..)
+            | Error::OtherSection(..)
+            | Error::AliasChanged(..)
+            | Error::NothingToUndo
+            | Error::Declined(_)
+            | Error::NotConfirmed(_)
+            | Error::NewConfig(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+/// Causes of wrapped errors
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::NoFile(_, e)
+            | Error::Command(e)
+            | Error::Output(e)
+            | Error::Prompt(e)
+            | Error::ConfigPath(e)
+            | Error::ConfigFileCreation(_, e)
+            | Error::ConfigFileWrite(_, e)
+            | Error::ConfigDirCreation(_, e)
+            | Error::History(_, e)
+            | Error::Allowlist(_, e) => Some(e),
+            Error::UnableToParse(_, e) => Some(e),
+            Error::UnableToSerialize(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 /// User readable error messages
+///
+/// The cause is not part of the message, it is available through [`error::Error::source`]
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let err = match self {
@@ -66,24 +191,37 @@ impl fmt::Display for Error {
                 "No configuration file found. Create one in this directory using `dipse init`"
                     .to_string()
             }
-            Error::NoFile(file_loc, e) => {
-                format!("Could not read file: {}\n{}", file_loc.display(), e)
+            Error::NoFile(file_loc, _) => {
+                format!("Could not read file: {}", file_loc.display())
             }
-            Error::ConfigFileWrite(path, e) => {
-                format!("Could not write to config file: {}\n{}", path.display(), e)
+            Error::ConfigFileWrite(path, _) => {
+                format!("Could not write to config file: {}", path.display())
             }
-            Error::UnableToParse(e) => {
-                format!("{}", e)
+            Error::UnableToParse(path, _) => {
+                format!("Could not parse config file: {}", path.display())
             }
-            Error::UnableToSerialize(e) => {
-                format!("{}", e)
+            Error::UnableToSerialize(_) => "Could not serialize config".to_string(),
+            Error::UnableToEdit(path, e) => {
+                format!("Could not change config file {}: {}", path.display(), e)
+            }
+            Error::UnableToImport(path, e) => {
+                format!("Could not import aliases from {}: {}", path.display(), e)
             }
             Error::CurrentDir => "Unable to access the current working directory.".to_string(),
             Error::NoConfigForPath(path) => {
-                format!("No entries found for path: {}", path.display())
+                format!(
+                    "No section of the config files covers {}\n\
+                     Create one with `dipse add --create-section <name> <cmd>`, \
+                     or add it to the global config with `--global --create-section`",
+                    path.display()
+                )
             }
-            Error::NoCmdStringFound(path, cmd) => {
-                format!("No command {} found for path: {}", cmd, path.display())
+            Error::NoCmdStringFound(path, cmd, suggestions) => {
+                let mut err = format!("No command {} found for path: {}", cmd, path.display());
+                if !suggestions.is_empty() {
+                    err.push_str(&format!("\nDid you mean: {}?", suggestions.join(", ")));
+                }
+                err
             }
             Error::CmdStringExists(path, cmd) => {
                 format!(
@@ -92,30 +230,84 @@ impl fmt::Display for Error {
                     path.display()
                 )
             }
-            Error::Command(e) => {
-                format!("{}", e)
+            Error::SectionExists(path, key) => {
+                format!("Section {:?} already exists in {}", key, path.display())
             }
-            Error::ConfigDirCreation(path, e) => {
+            Error::ReservedName(name) => {
                 format!(
-                    "Could not create configuration directory: {}\n{}",
-                    path.display(),
-                    e
+                    "{} is a section setting and can not be used as alias name",
+                    name
                 )
             }
-            Error::ConfigPath(e) => {
-                format!("{}", e)
+            Error::Discovered(name, source) => {
+                format!(
+                    "{} is discovered from {}, it is not in a config file",
+                    name, source
+                )
             }
-            Error::ConfigFileCreation(path, e) => {
+            Error::OtherSection(path, key, name) => {
                 format!(
-                    "Could not create configuration file: {}\n{}",
-                    path.display(),
-                    e
+                    "{} is defined in section {:?} of {}, not in the section of this directory",
+                    name,
+                    key,
+                    path.display()
                 )
             }
+            Error::Command(_) => "Could not run command".to_string(),
+            Error::ChildFailed(cmd, code) => {
+                format!("`{}` exited with code {}", cmd, code)
+            }
+            Error::Output(_) => "Could not write output".to_string(),
+            Error::Prompt(_) => "Could not ask in the terminal".to_string(),
+            Error::ConfigDirCreation(path, _) => {
+                format!(
+                    "Could not create configuration directory: {}",
+                    path.display()
+                )
+            }
+            Error::ConfigPath(_) => "Could not access configuration path".to_string(),
+            Error::ConfigFileCreation(path, _) => {
+                format!("Could not create configuration file: {}", path.display())
+            }
             Error::NewConfig(path) => {
                 format!("Empty configuration file. Please edit {}", path.display())
             }
             Error::ConfigDir => "Could not access config directory".to_string(),
+            Error::DataDir => "Could not access data directory".to_string(),
+            Error::History(path, _) => {
+                format!("Could not access the history in {}", path.display())
+            }
+            Error::AliasChanged(path, name) => {
+                format!(
+                    "{} in {} was changed since, not undoing",
+                    name,
+                    path.display()
+                )
+            }
+            Error::NothingToUndo => "No changes to undo for this directory".to_string(),
+            Error::Declined(cmd) => format!("Did not run `{}`", cmd),
+            Error::NotConfirmed(cmd) => {
+                format!(
+                    "`{}` asks for confirmation and there is no terminal to ask in\n\
+                     Run it with --yes, or set DIPSE_ASSUME_YES=1",
+                    cmd
+                )
+            }
+            Error::Allowlist(path, _) => {
+                format!("Could not access the allowlist in {}", path.display())
+            }
+            Error::NotAllowed(path, changed, diff) => {
+                let what = match changed {
+                    true => "changed since it was allowed",
+                    false => "is not allowed to run aliases yet",
+                };
+                format!(
+                    "{} {}\n{}Review it and run `dipse allow` to run its aliases",
+                    path.display(),
+                    what,
+                    diff
+                )
+            }
         };
         write!(f, "{}", err)
     }
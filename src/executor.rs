@@ -0,0 +1,197 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    error::Error,
+    utils::{CommandParams, Launch},
+};
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    process::{self, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Runs the commands that aliases resolve to
+///
+/// [`ShellExecutor`] is what the `dipse` binary uses. Other implementations can capture the
+/// output, record the commands instead of running them, or run them in a PTY.
+pub trait Executor {
+    /// Run a single command and wait for it, returning its exit code
+    /// Error:
+    /// - The command could not be started
+    fn execute(&mut self, cmd_params: &CommandParams) -> Result<i32, Error>;
+}
+
+/// Runs commands through `sh -c`, script files directly and inline scripts through a temporary
+/// file, with io inherited
+#[derive(Debug, Clone, Default)]
+pub struct ShellExecutor {
+    /// Working directory for the commands, the current directory if `None`
+    pub cwd: Option<PathBuf>,
+}
+
+impl ShellExecutor {
+    /// Executor running in the current directory
+    pub fn new() -> Self {
+        ShellExecutor::default()
+    }
+}
+
+impl Executor for ShellExecutor {
+    fn execute(&mut self, cmd_params: &CommandParams) -> Result<i32, Error> {
+        // Temporary file of an inline script, removed when the command is done
+        let mut script = None;
+        let mut command = match &cmd_params.launch {
+            Launch::Shell => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(format!("{}", cmd_params));
+                command
+            }
+            Launch::File(file) => {
+                let mut command = Command::new(file);
+                command.args(&cmd_params.params);
+                command
+            }
+            Launch::Script { interpreter, body } => {
+                let file = TempScript::create(body, interpreter.is_none())?;
+                let mut command = match interpreter {
+                    Some(i) => {
+                        let mut words = i.split_whitespace();
+                        let mut command = Command::new(words.next().unwrap_or("sh"));
+                        command.args(words);
+                        command.arg(&file.path);
+                        command
+                    }
+                    None => Command::new(&file.path),
+                };
+                command.args(&cmd_params.params);
+                script = Some(file);
+                command
+            }
+        };
+        command
+            .envs(&cmd_params.env)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit());
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+
+        let status = match command.status() {
+            Ok(s) => s,
+            Err(e) => return Err(Error::Command(e)),
+        };
+        drop(script);
+        Ok(exit_code(status))
+    }
+}
+
+/// Inline script written to a file only the current user can access, removed on drop
+struct TempScript {
+    path: PathBuf,
+}
+
+impl TempScript {
+    /// Write `body` to a new temporary file, executable if `executable`
+    /// Error:
+    /// - The file could not be created or written
+    fn create(body: &str, executable: bool) -> Result<Self, Error> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let path = env::temp_dir().join(format!(
+            "dipse-{}-{}-{}",
+            process::id(),
+            nanos,
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(if executable { 0o700 } else { 0o600 });
+        }
+        #[cfg(not(unix))]
+        let _ = executable;
+
+        let mut file = match options.open(&path) {
+            Err(e) => return Err(Error::Command(e)),
+            Ok(f) => f,
+        };
+        // Remove the file even if writing fails
+        let script = TempScript { path };
+        if let Err(e) = file
+            .write_all(body.as_bytes())
+            .and_then(|_| file.sync_all())
+        {
+            return Err(Error::Command(e));
+        }
+
+        Ok(script)
+    }
+}
+
+impl Drop for TempScript {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Records commands instead of running them
+///
+/// Every command succeeds. Used for `--no-op` and for testing alias resolution.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingExecutor {
+    /// Commands in the order they were given
+    pub commands: Vec<CommandParams>,
+}
+
+impl Executor for RecordingExecutor {
+    fn execute(&mut self, cmd_params: &CommandParams) -> Result<i32, Error> {
+        self.commands.push(cmd_params.clone());
+        Ok(0)
+    }
+}
+
+/// Exit code of a process, killed processes get 128 + signal like in sh
+#[cfg(unix)]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    match (status.code(), status.signal()) {
+        (Some(c), _) => c,
+        (None, Some(s)) => 128 + s,
+        (None, None) => 1,
+    }
+}
+
+/// Exit code of a process
+#[cfg(not(unix))]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
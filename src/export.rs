@@ -0,0 +1,466 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{model::Alias, shell::is_function_name, utils::Launch};
+use serde_json::{json, Map, Value};
+use std::{fmt::Write, str::FromStr};
+
+/// Formats the aliases of a directory can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Makefile with a phony target per alias
+    Make,
+    /// justfile with a recipe per alias
+    Just,
+    /// POSIX shell functions
+    Sh,
+    /// fish functions
+    Fish,
+    /// VS Code `tasks.json`
+    VscodeTasks,
+    /// JSON object of every alias, for scripts
+    Json,
+}
+
+impl Format {
+    /// Names accepted on the command line
+    pub const NAMES: &'static [&'static str] =
+        &["make", "just", "sh", "fish", "vscode-tasks", "json"];
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "make" => Ok(Format::Make),
+            "just" => Ok(Format::Just),
+            "sh" => Ok(Format::Sh),
+            "fish" => Ok(Format::Fish),
+            "vscode-tasks" => Ok(Format::VscodeTasks),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown format {:?}, expected one of {}",
+                s,
+                Format::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Render `aliases` in `format`
+///
+/// Returns the text and the aliases the format can not express, as `name: reason`.
+pub fn render(format: Format, aliases: &[&Alias]) -> (String, Vec<String>) {
+    match format {
+        Format::Make => make(aliases),
+        Format::Just => just(aliases),
+        Format::Sh => sh(aliases),
+        Format::Fish => fish(aliases),
+        Format::VscodeTasks => vscode_tasks(aliases),
+        Format::Json => (json(aliases), vec![]),
+    }
+}
+
+/// Quote `s` for a POSIX shell, words without special characters are left as they are
+pub fn shell_quote(s: &str) -> String {
+    let plain = s
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-+=.,:/@%".contains(c));
+    if plain && !s.is_empty() {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Command running the alias in a shell, `None` for inline scripts
+fn shell_cmd(alias: &Alias) -> Option<String> {
+    if alias.script.is_some() {
+        return None;
+    }
+    match &alias.file {
+        Some(f) => Some(shell_quote(&f.display().to_string())),
+        None => Some(alias.cmd.clone()),
+    }
+}
+
+/// `name: reason` for an alias left out of the export
+fn skip(alias: &Alias, reason: &str) -> String {
+    format!("{}: {}", alias.name, reason)
+}
+
+/// Reason for skipping an inline script
+const NO_SCRIPTS: &str = "inline scripts can not be exported to this format";
+/// Reason for skipping a name that would have to be quoted
+const BAD_NAME: &str = "the name is not valid in this format";
+
+/// Reason for skipping an alias that has to be confirmed
+const NO_CONFIRM: &str = "the confirmation can not be exported to this format";
+
+/// sh commands asking `question` and leaving with `leave 1` unless the answer is yes
+fn sh_prompt(question: &str, leave: &str) -> String {
+    format!(
+        "printf '%s [y/N] ' {} >&2; read -r answer; case \"$answer\" in [yY]*) ;; *) {} 1 ;; esac",
+        shell_quote(question),
+        leave
+    )
+}
+
+/// `line` for a justfile recipe, where `{{` starts an interpolation
+fn just_escape(line: &str) -> String {
+    line.replace("{{", "{{{{")
+}
+
+/// Whether `name` can be a make target or a just recipe as it is
+fn is_recipe_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// `export KEY='value'; ` for every variable of the alias
+fn sh_exports(alias: &Alias) -> String {
+    alias
+        .env
+        .iter()
+        .map(|(k, v)| format!("export {}={}; ", k, shell_quote(v)))
+        .collect()
+}
+
+fn make(aliases: &[&Alias]) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut skipped = vec![];
+
+    let names = aliases
+        .iter()
+        .filter(|a| a.script.is_none() && is_recipe_name(&a.name))
+        .map(|a| a.name.as_str())
+        .collect::<Vec<_>>();
+    let _ = writeln!(
+        out,
+        "# Generated by dipse export, pass arguments with ARGS=..."
+    );
+    let _ = writeln!(out, ".PHONY: {}", names.join(" "));
+
+    for alias in aliases {
+        let cmd = match shell_cmd(alias) {
+            Some(_) if !is_recipe_name(&alias.name) => {
+                skipped.push(skip(alias, BAD_NAME));
+                continue;
+            }
+            Some(c) => c,
+            None => {
+                skipped.push(skip(alias, NO_SCRIPTS));
+                continue;
+            }
+        };
+        let _ = writeln!(out);
+        match &alias.description {
+            Some(d) => {
+                let _ = writeln!(out, "{}: ## {}", alias.name, d);
+            }
+            None => {
+                let _ = writeln!(out, "{}:", alias.name);
+            }
+        }
+        if let Some(question) = alias.confirmation() {
+            let _ = writeln!(
+                out,
+                "\t@{}",
+                sh_prompt(&question, "exit").replace('$', "$$")
+            );
+        }
+        let line = format!("{}{}", sh_exports(alias), cmd).replace('$', "$$");
+        let _ = writeln!(out, "\t{} $(ARGS)", line);
+    }
+
+    (out, skipped)
+}
+
+fn just(aliases: &[&Alias]) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut skipped = vec![];
+    let _ = writeln!(out, "# Generated by dipse export");
+
+    for alias in aliases {
+        if !is_recipe_name(&alias.name) {
+            skipped.push(skip(alias, BAD_NAME));
+            continue;
+        }
+        let question = alias.confirmation();
+        // A shebang recipe is a single script, there is no line to ask on before it
+        if question.is_some() && alias.script.is_some() {
+            skipped.push(skip(alias, NO_CONFIRM));
+            continue;
+        }
+        let _ = writeln!(out);
+        if let Some(d) = &alias.description {
+            let _ = writeln!(out, "# {}", d);
+        }
+        let _ = writeln!(out, "{} *args:", alias.name);
+        match (alias.launch(), shell_cmd(alias)) {
+            (Launch::Script { interpreter, body }, _) => {
+                if let Some(i) = interpreter {
+                    let _ = writeln!(out, "    #!/usr/bin/env {}", i);
+                }
+                for line in body.lines() {
+                    let _ = writeln!(out, "    {}", just_escape(line));
+                }
+            }
+            (_, Some(cmd)) => {
+                if let Some(question) = question {
+                    let prompt = sh_prompt(&question, "exit");
+                    let _ = writeln!(out, "    @{}", just_escape(&prompt));
+                }
+                let line = format!("{}{}", sh_exports(alias), cmd);
+                let _ = writeln!(out, "    {} {{{{args}}}}", just_escape(&line));
+            }
+            (_, None) => {}
+        }
+    }
+
+    (out, skipped)
+}
+
+fn sh(aliases: &[&Alias]) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut skipped = vec![];
+    let _ = writeln!(out, "# Generated by dipse export");
+
+    for alias in aliases {
+        if !is_function_name(&alias.name) {
+            skipped.push(skip(alias, BAD_NAME));
+            continue;
+        }
+        let _ = writeln!(out);
+        if let Some(d) = &alias.description {
+            let _ = writeln!(out, "# {}", d);
+        }
+        let _ = writeln!(out, "{}() {{", alias.name);
+        if let Some(question) = alias.confirmation() {
+            let _ = writeln!(out, "    {}", sh_prompt(&question, "return"));
+        }
+        match (alias.launch(), shell_cmd(alias)) {
+            (Launch::Script { interpreter, body }, _) => {
+                // Like the kernel does for a shebang, the interpreter gets the script as a file
+                let interpreter = interpreter.unwrap_or_else(|| {
+                    let shebang = body.lines().next().unwrap_or_default();
+                    shebang.trim_start_matches("#!").trim().to_string()
+                });
+                let _ = writeln!(
+                    out,
+                    "    ( {}{} /dev/stdin \"$@\" <<'DIPSE_SCRIPT'\n{}\nDIPSE_SCRIPT\n    )",
+                    sh_exports(alias),
+                    interpreter,
+                    body.trim_end()
+                );
+            }
+            (_, Some(cmd)) => {
+                let _ = writeln!(out, "    ( {}{} \"$@\" )", sh_exports(alias), cmd);
+            }
+            (_, None) => {}
+        }
+        let _ = writeln!(out, "}}");
+    }
+
+    (out, skipped)
+}
+
+fn fish(aliases: &[&Alias]) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut skipped = vec![];
+    let _ = writeln!(out, "# Generated by dipse export");
+
+    for alias in aliases {
+        let cmd = match shell_cmd(alias) {
+            Some(_) if !is_function_name(&alias.name) => {
+                skipped.push(skip(alias, BAD_NAME));
+                continue;
+            }
+            Some(c) => c,
+            None => {
+                skipped.push(skip(alias, NO_SCRIPTS));
+                continue;
+            }
+        };
+        let _ = writeln!(out);
+        match &alias.description {
+            Some(d) => {
+                let _ = writeln!(
+                    out,
+                    "function {} --description {}",
+                    alias.name,
+                    shell_quote(d)
+                );
+            }
+            None => {
+                let _ = writeln!(out, "function {}", alias.name);
+            }
+        }
+        if let Some(question) = alias.confirmation() {
+            let _ = writeln!(
+                out,
+                "    read -l -P {} answer; and string match -qi 'y*' -- $answer; or return 1",
+                shell_quote(&format!("{} [y/N] ", question))
+            );
+        }
+        for (k, v) in &alias.env {
+            let _ = writeln!(out, "    set -lx {} {}", k, shell_quote(v));
+        }
+        // The command is written for sh, so let sh run it
+        let _ = writeln!(
+            out,
+            "    sh -c {} {} $argv",
+            shell_quote(&format!("{} \"$@\"", cmd)),
+            shell_quote(&alias.name)
+        );
+        let _ = writeln!(out, "end");
+    }
+
+    (out, skipped)
+}
+
+fn vscode_tasks(aliases: &[&Alias]) -> (String, Vec<String>) {
+    let mut tasks = vec![];
+    let mut skipped = vec![];
+
+    for alias in aliases {
+        let cmd = match shell_cmd(alias) {
+            Some(c) => c,
+            None => {
+                skipped.push(skip(alias, NO_SCRIPTS));
+                continue;
+            }
+        };
+        if alias.confirmation().is_some() {
+            skipped.push(skip(alias, NO_CONFIRM));
+            continue;
+        }
+        let mut task = Map::new();
+        task.insert("label".to_string(), json!(alias.name));
+        task.insert("type".to_string(), json!("shell"));
+        task.insert("command".to_string(), json!(cmd));
+        if let Some(d) = &alias.description {
+            task.insert("detail".to_string(), json!(d));
+        }
+        if !alias.env.is_empty() {
+            task.insert("options".to_string(), json!({ "env": alias.env }));
+        }
+        task.insert("problemMatcher".to_string(), json!([]));
+        tasks.push(Value::Object(task));
+    }
+
+    let tasks = json!({ "version": "2.0.0", "tasks": tasks });
+    (
+        serde_json::to_string_pretty(&tasks).unwrap_or_default() + "\n",
+        skipped,
+    )
+}
+
+fn json(aliases: &[&Alias]) -> String {
+    let mut map = Map::new();
+    for alias in aliases {
+        let mut entry = Map::new();
+        match &alias.script {
+            Some(script) => {
+                entry.insert("script".to_string(), json!(script));
+                if let Some(i) = &alias.interpreter {
+                    entry.insert("interpreter".to_string(), json!(i));
+                }
+            }
+            None => {
+                entry.insert("cmd".to_string(), json!(shell_cmd(alias)));
+            }
+        }
+        if let Some(d) = &alias.description {
+            entry.insert("description".to_string(), json!(d));
+        }
+        if !alias.env.is_empty() {
+            entry.insert("env".to_string(), json!(alias.env));
+        }
+        if let Some(question) = alias.confirmation() {
+            entry.insert("confirm".to_string(), json!(question));
+        }
+        entry.insert("source".to_string(), json!(alias.source.to_string()));
+        entry.insert("discovered".to_string(), json!(alias.discovered));
+        map.insert(alias.name.clone(), Value::Object(entry));
+    }
+    serde_json::to_string_pretty(&Value::Object(map)).unwrap_or_default() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Confirm;
+
+    fn alias() -> Alias {
+        let mut alias = Alias::new("build", "echo \"$HOME\" '{{x}}' $(ARGS)");
+        alias.env.insert("P".to_string(), "$HOME/x".to_string());
+        alias
+    }
+
+    #[test]
+    fn make_escapes_exports_and_the_command() {
+        let (text, skipped) = render(Format::Make, &[&alias()]);
+        assert!(skipped.is_empty());
+        assert!(text.contains("\texport P='$$HOME/x'; echo \"$$HOME\" '{{x}}' $$(ARGS) $(ARGS)\n"));
+    }
+
+    #[test]
+    fn just_escapes_interpolations_in_every_line() {
+        let mut confirmed = alias();
+        confirmed.confirm = Some(Confirm::Message("Deploy {{env}}?".to_string()));
+        let mut script = Alias::new("gen", "");
+        script.script = Some("echo '{{name}}'\necho \"$1\"".to_string());
+        script.interpreter = Some("bash".to_string());
+
+        let (text, skipped) = render(Format::Just, &[&confirmed, &script]);
+        assert!(skipped.is_empty());
+        assert!(text.contains("    @printf '%s [y/N] ' 'Deploy {{{{env}}?' >&2;"));
+        assert!(
+            text.contains("    export P='$HOME/x'; echo \"$HOME\" '{{{{x}}' $(ARGS) {{args}}\n")
+        );
+        assert!(text.contains("    #!/usr/bin/env bash\n    echo '{{{{name}}'\n    echo \"$1\"\n"));
+    }
+
+    #[test]
+    fn sh_keeps_the_command_as_written() {
+        let mut quoted = alias();
+        quoted.env.insert("Q".to_string(), "it's".to_string());
+        let (text, skipped) = render(Format::Sh, &[&quoted]);
+        assert!(skipped.is_empty());
+        assert!(text.contains(
+            "build() {\n    ( export P='$HOME/x'; export Q='it'\\''s'; echo \"$HOME\" '{{x}}' $(ARGS) \"$@\" )\n}\n"
+        ));
+    }
+
+    #[test]
+    fn formats_skip_what_they_can_not_express() {
+        let mut script = Alias::new("gen", "");
+        script.script = Some("echo hi".to_string());
+        let bad = Alias::new("a.b", "true");
+
+        let (text, skipped) = render(Format::Make, &[&script, &bad]);
+        assert_eq!(
+            skipped,
+            vec![format!("gen: {}", NO_SCRIPTS), format!("a.b: {}", BAD_NAME)]
+        );
+        assert!(text.contains(".PHONY: \n"));
+    }
+}
@@ -0,0 +1,646 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Every `add`, `update`, `delete` and `rename` is appended to a journal in the data directory,
+//! one JSON object per line. `copy`, `move` and `import` are recorded as the adds, updates and
+//! deletes they amount to. Undoing a change applies the opposite change and records it as well,
+//! so the journal always tells how a config file got to its current state.
+
+use crate::{
+    config::{lock_configs, write_atomic, ConfigSet, Lock},
+    error::Error,
+    model::{Alias, Config},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Entries kept in the journal, older ones are dropped
+const MAX_ENTRIES: usize = 1000;
+
+/// What happened to an alias
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// `dipse add`, the target of `copy` and `move`, or an imported alias
+    Add,
+    /// `dipse update`, or an imported alias that overwrote one
+    Update,
+    /// `dipse delete`, or the source of `move`
+    Delete,
+    /// `dipse rename`
+    Rename,
+}
+
+/// A change of an alias, one line of the journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Microseconds since the epoch when the change was made, identifies the entry
+    pub id: u64,
+    /// Canonical path of the config file
+    pub file: PathBuf,
+    /// Key of the section
+    pub section: PathBuf,
+    /// What happened
+    pub change: Change,
+    /// Name of the alias, the old name for renames
+    pub name: String,
+    /// New name for renames
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_name: Option<String>,
+    /// Value before the change, `None` for added aliases
+    pub old: Option<serde_json::Value>,
+    /// Value after the change, `None` for deleted aliases
+    pub new: Option<serde_json::Value>,
+    /// Id of the entry this change undid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<u64>,
+}
+
+/// Alias as stored in the journal
+fn value(alias: &Alias) -> serde_json::Value {
+    serde_json::to_value(alias).unwrap_or_default()
+}
+
+impl Entry {
+    /// Entry for a change of the alias `name` in the section `section` of `config`
+    ///
+    /// `old` and `new` are the alias before and after the change.
+    pub fn new(
+        config: &Config,
+        section: &Path,
+        change: Change,
+        old: Option<&Alias>,
+        new: Option<&Alias>,
+    ) -> Self {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let name = old.or(new).map(|a| a.name.clone()).unwrap_or_default();
+        let new_name = match (change, new) {
+            (Change::Rename, Some(a)) => Some(a.name.clone()),
+            _ => None,
+        };
+        Entry {
+            id,
+            file: config
+                .path
+                .canonicalize()
+                .unwrap_or_else(|_| config.path.clone()),
+            section: section.to_path_buf(),
+            change,
+            name,
+            new_name,
+            old: old.map(value),
+            new: new.map(value),
+            undoes: None,
+        }
+    }
+
+    /// Name of the alias after the change
+    pub fn current_name(&self) -> &str {
+        self.new_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Short form of a stored value for listings
+    fn summary(&self, value: &Option<serde_json::Value>) -> String {
+        let alias = value
+            .clone()
+            .and_then(|v| Alias::from_value(&self.name, v).ok());
+        match alias {
+            Some(a) => format!("{:?}", a.summary()),
+            None => "?".to_string(),
+        }
+    }
+
+    /// One line describing the change, like `update b: "make" -> "make -j4"`
+    pub fn describe(&self) -> String {
+        let change = match self.change {
+            Change::Add => format!("add {} = {}", self.name, self.summary(&self.new)),
+            Change::Update => format!(
+                "update {}: {} -> {}",
+                self.name,
+                self.summary(&self.old),
+                self.summary(&self.new)
+            ),
+            Change::Delete => format!("delete {} = {}", self.name, self.summary(&self.old)),
+            Change::Rename => format!("rename {} to {}", self.name, self.current_name()),
+        };
+        format!(
+            "{} in {:?} of {}",
+            change,
+            self.section,
+            self.file.display()
+        )
+    }
+
+    /// How long ago the change was made, like `5 minutes ago`
+    pub fn age(&self) -> String {
+        let then = UNIX_EPOCH + Duration::from_micros(self.id);
+        let secs = SystemTime::now()
+            .duration_since(then)
+            .unwrap_or_default()
+            .as_secs();
+        let (n, unit) = match secs {
+            0..=59 => return "just now".to_string(),
+            60..=3599 => (secs / 60, "minute"),
+            3600..=86399 => (secs / 3600, "hour"),
+            _ => (secs / 86400, "day"),
+        };
+        format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+    }
+
+    /// Apply the opposite of this change to `config`, which must be the file of the entry
+    ///
+    /// Returns the entry for the undo.
+    /// Error:
+    /// - The alias was changed again since
+    /// - The section does not exist any more
+    pub fn undo(&self, config: &mut Config) -> Result<Entry, Error> {
+        let changed = || Error::AliasChanged(config.path.clone(), self.current_name().to_string());
+        let current = config
+            .section(&self.section)
+            .and_then(|s| s.get(self.current_name()))
+            .map(value);
+        if current != self.new {
+            return Err(changed());
+        }
+        if self.change == Change::Rename
+            && config
+                .section(&self.section)
+                .is_some_and(|s| s.get(&self.name).is_some())
+        {
+            return Err(changed());
+        }
+
+        let old = match self.old.clone().map(|v| Alias::from_value(&self.name, v)) {
+            Some(Err(e)) => return Err(Error::UnableToEdit(config.path.clone(), e)),
+            Some(Ok(a)) => Some(a),
+            None => None,
+        };
+        let change = match (self.change, old) {
+            (Change::Add, _) => {
+                config.delete(&self.section, &self.name)?;
+                Change::Delete
+            }
+            (Change::Rename, _) => {
+                config.rename(&self.section, self.current_name(), &self.name)?;
+                Change::Rename
+            }
+            (Change::Delete, Some(old)) => {
+                config.restore(&self.section, old)?;
+                Change::Add
+            }
+            (Change::Update, Some(old)) => {
+                config.restore(&self.section, old)?;
+                Change::Update
+            }
+            (_, None) => return Err(changed()),
+        };
+
+        Ok(Entry {
+            name: self.current_name().to_string(),
+            new_name: self.new_name.as_ref().map(|_| self.name.clone()),
+            old: self.new.clone(),
+            new: self.old.clone(),
+            undoes: Some(self.id),
+            change,
+            ..Entry::new(config, &self.section, change, None, None)
+        })
+    }
+}
+
+/// Path of the journal, `$XDG_DATA_HOME/dipse/history.jsonl`
+/// Error:
+/// - Data directory could not be found
+pub fn journal_path() -> Result<PathBuf, Error> {
+    match dirs::data_dir() {
+        Some(d) => Ok(d.join("dipse").join("history.jsonl")),
+        None => Err(Error::DataDir),
+    }
+}
+
+/// All entries of the journal, oldest first
+///
+/// Lines that can not be parsed are skipped.
+/// Error:
+/// - The journal exists but could not be read
+pub fn read() -> Result<Vec<Entry>, Error> {
+    read_from(&journal_path()?)
+}
+
+fn read_from(path: &Path) -> Result<Vec<Entry>, Error> {
+    let text = match fs::read_to_string(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(Error::History(path.to_path_buf(), e)),
+        Ok(t) => t,
+    };
+    Ok(text
+        .lines()
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect())
+}
+
+/// Append `entries` to the journal, dropping the oldest entries once it is full
+/// Error:
+/// - The journal could not be locked or written
+pub fn record(entries: Vec<Entry>) -> Result<(), Error> {
+    record_to(&journal_path()?, entries)
+}
+
+fn record_to(path: &Path, mut entries: Vec<Entry>) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(Error::History(path.to_path_buf(), e));
+        }
+    }
+    let _lock = Lock::acquire(path)?;
+    let all = read_from(path)?;
+
+    // Ids have to be unique, changes made in the same microsecond get the next one
+    let mut last = all.last().map_or(0, |e| e.id);
+    for entry in &mut entries {
+        entry.id = entry.id.max(last + 1);
+        last = entry.id;
+    }
+
+    let lines = |entries: &[Entry]| {
+        entries
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap_or_default() + "\n")
+            .collect::<String>()
+    };
+    let res = if all.len() + entries.len() > MAX_ENTRIES {
+        let mut all = all;
+        all.extend(entries);
+        write_atomic(path, &lines(&all[all.len() - MAX_ENTRIES..]))
+    } else {
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .and_then(|mut f| f.write_all(lines(&entries).as_bytes()))
+    };
+    match res {
+        Err(e) => Err(Error::History(path.to_path_buf(), e)),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Add changes to the journal, the change is made already so failures are only reported
+pub fn record_or_warn(entries: Vec<Entry>) {
+    if let Err(e) = record(entries) {
+        eprintln!("warning: the change is not in the history: {}", e);
+    }
+}
+
+/// Changes to `files` that can be undone, newest first
+///
+/// Undos themselves and changes that were undone already are left out, so undoing again goes
+/// further back.
+pub fn undoable<'a>(entries: &'a [Entry], files: &[PathBuf]) -> Vec<&'a Entry> {
+    entries
+        .iter()
+        .rev()
+        .filter(|e| files.contains(&e.file))
+        .filter(|e| e.undoes.is_none())
+        .filter(|e| !entries.iter().any(|u| u.undoes == Some(e.id)))
+        .collect()
+}
+
+/// Changes undone by [`undo_last`]
+#[derive(Debug)]
+pub struct Undone<'a> {
+    /// Entries that were undone, newest first
+    pub entries: Vec<&'a Entry>,
+    /// Entries recording the undos, to be recorded once the files are saved
+    pub undos: Vec<Entry>,
+    /// Paths of the files of the set that changed
+    pub changed: Vec<PathBuf>,
+    /// `change: reason` for each change that could not be undone any more
+    pub skipped: Vec<String>,
+}
+
+/// Undo the last `n` changes of `entries` to the files of `set`
+///
+/// A change that can not be undone any more is skipped and an older one is undone instead. The
+/// files are changed in `set` only, saving them is up to the caller.
+pub fn undo_last<'a>(set: &mut ConfigSet, entries: &'a [Entry], n: usize) -> Undone<'a> {
+    let files = set.canonical_paths();
+    let mut undone = Undone {
+        entries: vec![],
+        undos: vec![],
+        changed: vec![],
+        skipped: vec![],
+    };
+    for entry in undoable(entries, &files) {
+        if undone.entries.len() == n {
+            break;
+        }
+        let idx = files.iter().position(|f| *f == entry.file).unwrap();
+        match entry.undo(&mut set.files[idx]) {
+            Err(e) => undone.skipped.push(format!("{}: {}", entry.describe(), e)),
+            Ok(u) => {
+                undone.undos.push(u);
+                undone.changed.push(set.files[idx].path.clone());
+                undone.entries.push(entry);
+            }
+        }
+    }
+    undone
+}
+
+/// Lines of `dipse history`, newest first, for the config files of `pwd` or every file with `all`
+///
+/// Changes that can be undone are numbered in the order `dipse undo` takes them.
+/// Error:
+/// - A config file or the journal could not be read
+pub fn listing(config_path: Option<PathBuf>, pwd: &Path, all: bool) -> Result<Vec<String>, Error> {
+    let set = ConfigSet::load(config_path, pwd)?;
+    let files = set.canonical_paths();
+    let entries = read()?;
+    let undoable = undoable(&entries, &files);
+
+    let mut lines = vec![];
+    let mut n = 0;
+    for entry in entries.iter().rev() {
+        if !all && !files.contains(&entry.file) {
+            continue;
+        }
+        let number = match undoable.contains(&entry) {
+            true => {
+                n += 1;
+                n.to_string()
+            }
+            false => String::new(),
+        };
+        let note = match entry.undoes {
+            Some(_) => " (undo)",
+            None if entries.iter().any(|u| u.undoes == Some(entry.id)) => " (undone)",
+            None => "",
+        };
+        lines.push(format!(
+            "{:>3}  {:<14} {}{}",
+            number,
+            entry.age(),
+            entry.describe(),
+            note
+        ));
+    }
+    Ok(lines)
+}
+
+/// Undo the last `n` changes to the config files of `pwd` with [`undo_last`] and save them
+///
+/// Changes that are skipped are reported on stderr. Returns a line for every undone change.
+/// Error:
+/// - A config file could not be locked, loaded or saved, or the journal could not be read
+/// - No change could be undone
+pub fn undo(config_path: Option<PathBuf>, pwd: &Path, n: usize) -> Result<Vec<String>, Error> {
+    let mut locks = vec![];
+    lock_configs(&mut locks, config_path.as_deref(), pwd)?;
+    let mut set = ConfigSet::load(config_path, pwd)?;
+    let entries = read()?;
+    let undone = undo_last(&mut set, &entries, n);
+    for skipped in &undone.skipped {
+        eprintln!("warning: skipped {}", skipped);
+    }
+    if undone.entries.is_empty() {
+        return Err(Error::NothingToUndo);
+    }
+    set.save_changed(undone.changed.iter().map(|p| p.as_path()))?;
+    record_or_warn(undone.undos);
+
+    Ok(undone
+        .entries
+        .iter()
+        .map(|e| format!("undid {}", e.describe()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_config, utils::test_dir};
+
+    const TEXT: &str = "[\".\"]\nb = \"make\"\nt = \"make test\"\n\n[sub]\nr = \"make run\"\n";
+
+    fn set() -> ConfigSet {
+        ConfigSet {
+            files: vec![parse_config(Path::new("/project/.d.toml"), TEXT).unwrap()],
+        }
+    }
+
+    /// Give the entries increasing ids, like [`record`] does
+    fn numbered(entries: Vec<Entry>) -> Vec<Entry> {
+        entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, e)| Entry {
+                id: i as u64 + 1,
+                ..e
+            })
+            .collect()
+    }
+
+    /// Command of the alias `name` in the section `key`
+    fn cmd(set: &ConfigSet, key: &str, name: &str) -> Option<String> {
+        let section = set.files[0].section(Path::new(key))?;
+        section.get(name).map(|a| a.cmd.clone())
+    }
+
+    #[test]
+    fn undo_reverts_each_kind_of_change() {
+        let mut set = set();
+        let config = &mut set.files[0];
+        let (root, sub) = (Path::new("."), Path::new("sub"));
+        let mut entries = vec![];
+
+        let x = Alias::new("x", "make x");
+        config.add(root, x.clone()).unwrap();
+        entries.push(Entry::new(config, root, Change::Add, None, Some(&x)));
+        let old = config.section(root).unwrap().get("b").cloned();
+        config.update(root, "b", "make -j4".to_string()).unwrap();
+        let new = config.section(root).unwrap().get("b");
+        entries.push(Entry::new(config, root, Change::Update, old.as_ref(), new));
+        let old = config.delete(root, "t").unwrap();
+        entries.push(Entry::new(config, root, Change::Delete, Some(&old), None));
+        let old = config.section(sub).unwrap().get("r").cloned();
+        config.rename(sub, "r", "run").unwrap();
+        let new = config.section(sub).unwrap().get("run");
+        entries.push(Entry::new(config, sub, Change::Rename, old.as_ref(), new));
+
+        let entries = numbered(entries);
+        let undone = undo_last(&mut set, &entries, 4);
+        assert!(undone.skipped.is_empty());
+        assert_eq!(
+            undone.entries.iter().map(|e| e.id).collect::<Vec<_>>(),
+            [4, 3, 2, 1]
+        );
+        let changes = undone.undos.iter().map(|u| u.change).collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [Change::Rename, Change::Add, Change::Update, Change::Delete]
+        );
+        assert!(undone
+            .changed
+            .iter()
+            .all(|p| p == Path::new("/project/.d.toml")));
+
+        assert_eq!(cmd(&set, ".", "x"), None);
+        assert_eq!(cmd(&set, ".", "b").as_deref(), Some("make"));
+        assert_eq!(cmd(&set, ".", "t").as_deref(), Some("make test"));
+        assert_eq!(cmd(&set, "sub", "r").as_deref(), Some("make run"));
+        assert_eq!(cmd(&set, "sub", "run"), None);
+    }
+
+    #[test]
+    fn undo_reverts_copies_moves_and_imports() {
+        let mut set = set();
+        let path = PathBuf::from("/project/.d.toml");
+        let (root, sub) = (Path::new("."), Path::new("sub"));
+        let mut entries = vec![];
+
+        // copy b to sub, then move t to sub, recorded as `dipse copy` and `dipse move` do
+        let b = cmd(&set, ".", "b").map(|c| Alias::new("b", &c)).unwrap();
+        let b = set
+            .transfer(&b, (&path, root), (&path, sub), false, false)
+            .unwrap();
+        entries.push(Entry::new(&set.files[0], sub, Change::Add, None, Some(&b)));
+        let t = cmd(&set, ".", "t").map(|c| Alias::new("t", &c)).unwrap();
+        let t = set
+            .transfer(&t, (&path, root), (&path, sub), true, false)
+            .unwrap();
+        entries.push(Entry::new(&set.files[0], sub, Change::Add, None, Some(&t)));
+        entries.push(Entry::new(
+            &set.files[0],
+            root,
+            Change::Delete,
+            Some(&t),
+            None,
+        ));
+
+        // an import that adds one alias and overwrites another
+        let config = &mut set.files[0];
+        let new = Alias::new("lint", "cargo clippy");
+        config.add(root, new.clone()).unwrap();
+        entries.push(Entry::new(config, root, Change::Add, None, Some(&new)));
+        let old = config.section(sub).unwrap().get("r").cloned();
+        config.restore(sub, Alias::new("r", "cargo run")).unwrap();
+        let new = config.section(sub).unwrap().get("r");
+        entries.push(Entry::new(config, sub, Change::Update, old.as_ref(), new));
+
+        let entries = numbered(entries);
+        let undone = undo_last(&mut set, &entries, entries.len());
+        assert!(undone.skipped.is_empty());
+        assert_eq!(undone.entries.len(), 5);
+        assert_eq!(aliases(&set), aliases(&self::set()));
+    }
+
+    /// Section keys, names and commands of the aliases in the text of the file
+    fn aliases(set: &ConfigSet) -> Vec<(PathBuf, String, String)> {
+        let config = parse_config(&set.files[0].path, &set.files[0].text).unwrap();
+        let mut aliases = vec![];
+        for section in config.sections {
+            for alias in section.aliases {
+                aliases.push((section.key.clone(), alias.name, alias.cmd));
+            }
+        }
+        aliases.sort();
+        aliases
+    }
+
+    #[test]
+    fn undo_goes_further_back_each_time() {
+        let mut set = set();
+        let root = Path::new(".");
+        let mut entries = vec![];
+        for cmd in ["make -j2", "make -j4"] {
+            let config = &mut set.files[0];
+            let old = config.section(root).unwrap().get("b").cloned();
+            config.update(root, "b", cmd.to_string()).unwrap();
+            let new = config.section(root).unwrap().get("b");
+            entries.push(Entry::new(config, root, Change::Update, old.as_ref(), new));
+        }
+        let mut entries = numbered(entries);
+
+        let undone = undo_last(&mut set, &entries, 1);
+        assert_eq!(undone.entries[0].id, 2);
+        let undos = undone.undos;
+        assert_eq!(cmd(&set, ".", "b").as_deref(), Some("make -j2"));
+        entries.extend(undos.into_iter().map(|u| Entry { id: 3, ..u }));
+
+        let undone = undo_last(&mut set, &entries, 1);
+        assert_eq!(undone.entries[0].id, 1);
+        assert_eq!(cmd(&set, ".", "b").as_deref(), Some("make"));
+        entries.extend(undone.undos.into_iter().map(|u| Entry { id: 4, ..u }));
+
+        let undone = undo_last(&mut set, &entries, 1);
+        assert!(undone.entries.is_empty());
+    }
+
+    #[test]
+    fn undo_skips_changes_to_relocated_sections() {
+        let mut set = set();
+        let config = &mut set.files[0];
+        let (root, sub) = (Path::new("."), Path::new("sub"));
+        let mut entries = vec![];
+
+        let x = Alias::new("x", "make x");
+        config.add(root, x.clone()).unwrap();
+        entries.push(Entry::new(config, root, Change::Add, None, Some(&x)));
+        let y = Alias::new("y", "make y");
+        config.add(sub, y.clone()).unwrap();
+        entries.push(Entry::new(config, sub, Change::Add, None, Some(&y)));
+        config.rename_section(sub, Path::new("moved")).unwrap();
+
+        let entries = numbered(entries);
+        let undone = undo_last(&mut set, &entries, 1);
+        assert_eq!(undone.skipped.len(), 1);
+        assert!(undone.skipped[0].starts_with("add y = \"make y\" in \"sub\""));
+        assert_eq!(undone.entries[0].id, 1);
+        assert_eq!(cmd(&set, ".", "x"), None);
+        assert_eq!(cmd(&set, "moved", "y").as_deref(), Some("make y"));
+    }
+
+    #[test]
+    fn record_keeps_the_newest_entries_with_unique_ids() {
+        let path = test_dir("history").join("history.jsonl");
+        let config = parse_config(Path::new("/project/.d.toml"), TEXT).unwrap();
+        let alias = Alias::new("x", "make x");
+        let entry = Entry::new(&config, Path::new("."), Change::Add, None, Some(&alias));
+
+        record_to(&path, vec![entry.clone(); MAX_ENTRIES - 1]).unwrap();
+        assert_eq!(read_from(&path).unwrap().len(), MAX_ENTRIES - 1);
+        record_to(&path, vec![entry.clone(); 5]).unwrap();
+
+        let ids = read_from(&path)
+            .unwrap()
+            .iter()
+            .map(|e| e.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids.len(), MAX_ENTRIES);
+        assert_eq!(ids[0], entry.id + 4);
+        assert!(ids.windows(2).all(|w| w[1] == w[0] + 1));
+    }
+}
@@ -0,0 +1,517 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    config::{lock_configs, ConfigSet},
+    discover::Tool,
+    error::Error,
+    export::shell_quote,
+    history::{self, Change, Entry},
+    model::{Alias, Config, Source},
+    resolver::Resolver,
+};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Formats aliases can be imported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `scripts` of `package.json`
+    PackageJson,
+    /// Targets of a Makefile
+    Makefile,
+    /// Tasks of `.vscode/tasks.json`
+    Vscode,
+    /// Recipes of a justfile
+    Justfile,
+    /// `alias name='cmd'` lines of a shell rc file
+    Bashrc,
+}
+
+impl Format {
+    /// Names accepted on the command line
+    pub const NAMES: &'static [&'static str] =
+        &["package.json", "makefile", "vscode", "justfile", "bashrc"];
+
+    /// Prefix for imported names that are taken, with [`Conflict::Prefix`]
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Format::PackageJson => "npm-",
+            Format::Makefile => "make-",
+            Format::Vscode => "vscode-",
+            Format::Justfile => "just-",
+            Format::Bashrc => "sh-",
+        }
+    }
+
+    /// File read when no path is given, relative to the current directory
+    pub fn default_path(self) -> PathBuf {
+        match self {
+            Format::PackageJson => PathBuf::from("package.json"),
+            Format::Makefile => PathBuf::from("Makefile"),
+            Format::Vscode => Path::new(".vscode").join("tasks.json"),
+            Format::Justfile => PathBuf::from("justfile"),
+            Format::Bashrc => dirs::home_dir().unwrap_or_default().join(".bashrc"),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::PackageJson => "package.json",
+            Format::Makefile => "makefile",
+            Format::Vscode => "vscode",
+            Format::Justfile => "justfile",
+            Format::Bashrc => "bashrc",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "package.json" | "npm" => Ok(Format::PackageJson),
+            "makefile" | "make" => Ok(Format::Makefile),
+            "vscode" => Ok(Format::Vscode),
+            "justfile" | "just" => Ok(Format::Justfile),
+            "bashrc" | "zshrc" | "shrc" => Ok(Format::Bashrc),
+            _ => Err(format!(
+                "unknown format {:?}, expected one of {}",
+                s,
+                Format::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// What to do with an imported alias whose name is already taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// Keep the existing alias
+    Skip,
+    /// Replace the existing alias
+    Overwrite,
+    /// Add the imported alias with a prefix
+    Prefix,
+}
+
+impl FromStr for Conflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Conflict::Skip),
+            "overwrite" => Ok(Conflict::Overwrite),
+            "prefix" => Ok(Conflict::Prefix),
+            _ => Err(format!(
+                "unknown conflict handling {:?}, expected skip, overwrite or prefix",
+                s
+            )),
+        }
+    }
+}
+
+/// Read the aliases defined in `path`
+///
+/// Returns the aliases and the entries that can not be imported, with the reason.
+/// Error:
+/// - Could not read file
+/// - The file is not valid for the format
+pub fn read_aliases(format: Format, path: &Path) -> Result<(Vec<Alias>, Vec<String>), Error> {
+    let text = match fs::read_to_string(path) {
+        Err(e) => return Err(Error::NoFile(path.to_path_buf(), e)),
+        Ok(t) => t,
+    };
+
+    let aliases = match format {
+        Format::PackageJson => tool_aliases(Tool::Npm, path, &text).map(|a| (a, vec![])),
+        Format::Makefile => tool_aliases(Tool::Make, path, &text).map(|a| (a, vec![])),
+        Format::Justfile => tool_aliases(Tool::Just, path, &text).map(|a| (a, vec![])),
+        Format::Vscode => vscode_tasks(path, &text),
+        Format::Bashrc => Ok((shell_aliases(path, &text), vec![])),
+    };
+    match aliases {
+        Err(e) => Err(Error::UnableToImport(path.to_path_buf(), e)),
+        Ok(a) => Ok(a),
+    }
+}
+
+/// Tasks of a tool file as aliases running the tool
+fn tool_aliases(tool: Tool, path: &Path, text: &str) -> Result<Vec<Alias>, String> {
+    Ok(tool
+        .tasks(text)?
+        .into_iter()
+        .map(|task| Alias {
+            description: task.description,
+            source: Source::new(path, task.line),
+            ..Alias::new(&task.name, &tool.command(&task.name))
+        })
+        .collect())
+}
+
+/// Tasks of a VS Code `tasks.json`, named after their label, and the tasks that are skipped
+///
+/// Only tasks with a `command` can be imported, `npm` tasks and others that VS Code runs itself
+/// are skipped.
+fn vscode_tasks(path: &Path, text: &str) -> Result<(Vec<Alias>, Vec<String>), String> {
+    let tasks = match serde_json::from_str::<serde_json::Value>(&strip_jsonc(text)) {
+        Err(e) => return Err(e.to_string()),
+        Ok(t) => t,
+    };
+    let tasks = match tasks.get("tasks").and_then(|t| t.as_array()) {
+        Some(t) => t,
+        None => return Ok((vec![], vec![])),
+    };
+
+    let mut aliases = vec![];
+    let mut skipped = vec![];
+    for task in tasks {
+        let label = match task.get("label").and_then(|l| l.as_str()) {
+            Some(l) => l,
+            None => continue,
+        };
+        let command = match task.get("command").and_then(|c| c.as_str()) {
+            Some(c) => c,
+            None => {
+                skipped.push(format!("{}: the task has no command", label));
+                continue;
+            }
+        };
+
+        // `command` is a command line, every arg is a single word
+        let mut cmd = command.to_string();
+        for arg in task
+            .get("args")
+            .and_then(|a| a.as_array())
+            .into_iter()
+            .flatten()
+        {
+            if let Some(arg) = arg.as_str() {
+                cmd.push(' ');
+                cmd.push_str(&shell_quote(arg));
+            }
+        }
+
+        let env = task
+            .pointer("/options/env")
+            .and_then(|e| e.as_object())
+            .into_iter()
+            .flatten()
+            .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+            .collect::<BTreeMap<_, _>>();
+
+        let name = label
+            .split(|c: char| c.is_whitespace() || c == ':')
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        aliases.push(Alias {
+            description: task
+                .get("detail")
+                .and_then(|d| d.as_str())
+                .map(|d| d.to_string()),
+            env,
+            source: Source::new(path, 0),
+            ..Alias::new(&name, &cmd)
+        });
+    }
+    Ok((aliases, skipped))
+}
+
+/// JSON with comments to JSON: drops `//` and `/* */` comments and trailing commas
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            (']', _) | ('}', _) => {
+                let trimmed = out.trim_end().len();
+                if out[..trimmed].ends_with(',') {
+                    out.truncate(trimmed - 1);
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// `alias name='cmd'` lines of a shell rc file
+fn shell_aliases(path: &Path, text: &str) -> Vec<Alias> {
+    let mut aliases = vec![];
+
+    for (idx, line) in text.lines().enumerate() {
+        let rest = match line.trim().strip_prefix("alias ") {
+            Some(r) => r.trim_start(),
+            None => continue,
+        };
+        // `alias -g name=...` in zsh
+        let rest = rest
+            .split_once(' ')
+            .filter(|(flag, _)| flag.starts_with('-'))
+            .map_or(rest, |(_, r)| r.trim_start());
+
+        let (name, value) = match rest.split_once('=') {
+            Some(a) => a,
+            None => continue,
+        };
+        let cmd = match value.chars().next() {
+            Some(q @ '\'') | Some(q @ '"') => match value[1..].find(q) {
+                Some(end) => &value[1..end + 1],
+                None => continue,
+            },
+            _ => value.split_whitespace().next().unwrap_or(""),
+        };
+        if name.is_empty() || cmd.is_empty() {
+            continue;
+        }
+
+        aliases.push(Alias {
+            source: Source::new(path, idx + 1),
+            ..Alias::new(name, cmd)
+        });
+    }
+
+    aliases
+}
+
+/// Import the aliases of the file at `path`, or the usual file of `format`, into the most
+/// specific section for `pwd`
+///
+/// Returns a line for every alias telling what happened to it, followed by the parts of the
+/// file that could not be imported.
+/// Error:
+/// - The file could not be read or parsed
+/// - A config file could not be locked, loaded or saved, or no section applies to `pwd`
+pub fn import(
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    format: Format,
+    path: Option<PathBuf>,
+    on_conflict: Conflict,
+    prefix: Option<String>,
+) -> Result<Vec<String>, Error> {
+    let path = path.unwrap_or_else(|| format.default_path());
+    let (aliases, skipped) = read_aliases(format, &path)?;
+    let prefix = prefix.unwrap_or_else(|| format.prefix().to_string());
+
+    let mut locks = vec![];
+    lock_configs(&mut locks, config_path.as_deref(), pwd)?;
+    let mut set = ConfigSet::load(config_path, pwd)?;
+    let (config_path, key) = {
+        let resolution = Resolver::new(&set).resolve(pwd)?;
+        resolution.print_warnings();
+
+        let best = resolution.best()?;
+        (best.config.path.clone(), best.section.key.clone())
+    };
+    let config = set.file_mut(&config_path).unwrap();
+
+    let mut lines = vec![];
+    let mut entries = vec![];
+    for alias in aliases {
+        lines.push(import_alias(
+            config,
+            &key,
+            alias,
+            on_conflict,
+            &prefix,
+            &mut entries,
+        )?);
+    }
+    lines.extend(
+        skipped
+            .into_iter()
+            .map(|reason| format!("skipped {}", reason)),
+    );
+
+    config.save()?;
+    history::record_or_warn(entries);
+    Ok(lines)
+}
+
+/// Add an imported alias like `dipse add` does, resolving name conflicts with `on_conflict`
+///
+/// Returns a line describing what happened, the change is added to `entries` for the history.
+pub fn import_alias(
+    config: &mut Config,
+    key: &Path,
+    alias: Alias,
+    on_conflict: Conflict,
+    prefix: &str,
+    entries: &mut Vec<Entry>,
+) -> Result<String, Error> {
+    let name = alias.name.clone();
+    match config.add(key, alias.clone()) {
+        Ok(()) => {
+            entries.push(Entry::new(config, key, Change::Add, None, Some(&alias)));
+            return Ok(format!("added {}", name));
+        }
+        Err(Error::ReservedName(_)) => {
+            return Ok(format!("skipped {}: name is a section setting", name))
+        }
+        Err(Error::CmdStringExists(..)) => {}
+        Err(e) => return Err(e),
+    }
+
+    match on_conflict {
+        Conflict::Skip => Ok(format!("skipped {}: already exists", name)),
+        Conflict::Overwrite => {
+            let old = config.delete(key, &name)?;
+            config.add(key, alias.clone())?;
+            entries.push(Entry::new(
+                config,
+                key,
+                Change::Update,
+                Some(&old),
+                Some(&alias),
+            ));
+            Ok(format!("overwrote {}", name))
+        }
+        Conflict::Prefix => {
+            let prefixed = format!("{}{}", prefix, name);
+            let alias = Alias {
+                name: prefixed.clone(),
+                ..alias
+            };
+            match config.add(key, alias.clone()) {
+                Ok(()) => {
+                    entries.push(Entry::new(config, key, Change::Add, None, Some(&alias)));
+                    Ok(format!("added {} as {}", name, prefixed))
+                }
+                Err(Error::CmdStringExists(..)) => {
+                    Ok(format!("skipped {}: {} already exists", name, prefixed))
+                }
+                Err(e) => Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_jsonc_drops_comments_and_trailing_commas() {
+        let text =
+            "{\n  // tasks\n  \"a\": [1, 2,], /* inline */\n  \"b\": \"http://x/* y */\",\n}";
+        let value = serde_json::from_str::<serde_json::Value>(&strip_jsonc(text)).unwrap();
+
+        assert_eq!(value["a"], serde_json::json!([1, 2]));
+        assert_eq!(value["b"], "http://x/* y */");
+    }
+
+    #[test]
+    fn strip_jsonc_keeps_escaped_quotes() {
+        let text = r#"{"a": "say \"hi\" // not a comment",}"#;
+        assert_eq!(strip_jsonc(text), r#"{"a": "say \"hi\" // not a comment"}"#);
+    }
+
+    #[test]
+    fn shell_aliases_read_quoted_and_bare_values() {
+        let text = "# aliases\n\
+                    alias ll='ls -la'\n\
+                    alias gs=\"git status\"\n\
+                    alias -g G='| grep'\n\
+                    alias v=nvim\n\
+                    alias broken='no end\n\
+                    export PATH=/bin\n";
+        let aliases = shell_aliases(Path::new("/home/.bashrc"), text);
+
+        let found = aliases
+            .iter()
+            .map(|a| (a.name.as_str(), a.cmd.as_str(), a.source.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("ll", "ls -la", 2),
+                ("gs", "git status", 3),
+                ("G", "| grep", 4),
+                ("v", "nvim", 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn vscode_tasks_quote_args_and_report_tasks_without_command() {
+        let text = r#"{
+            "version": "2.0.0",
+            "tasks": [
+                {
+                    "label": "build: release",
+                    "command": "cargo build",
+                    "args": ["--release", "a b"],
+                    "detail": "Optimized build",
+                    "options": { "env": { "RUSTFLAGS": "-D warnings" } },
+                },
+                { "label": "npm: lint", "type": "npm", "script": "lint" },
+            ],
+        }"#;
+        let (aliases, skipped) = vscode_tasks(Path::new("tasks.json"), text).unwrap();
+
+        assert_eq!(aliases.len(), 1);
+        assert_eq!(aliases[0].name, "build-release");
+        assert_eq!(aliases[0].cmd, "cargo build --release 'a b'");
+        assert_eq!(aliases[0].description.as_deref(), Some("Optimized build"));
+        assert_eq!(aliases[0].env["RUSTFLAGS"], "-D warnings");
+        assert_eq!(skipped, ["npm: lint: the task has no command"]);
+    }
+}
@@ -23,281 +23,553 @@
 //! projects. A `run` alias could point to `cargo run` on a rust project, while on a NodeJS
 //! project, it might point to `npm run start`.
 //!
-//! ## Configuration
+//! ## Library
 //!
-//! TODO
+//! The `dipse` binary is a thin layer over three types:
+//!
+//! - [`ConfigSet`] loads the config files that apply to a directory
+//! - [`Resolver`] finds the sections and aliases for a directory
+//! - [`Executor`] runs the resolved commands, [`ShellExecutor`] is the default implementation
+//!
+//! ```no_run
+//! use dipse::{ConfigSet, Executor, Resolver, ShellExecutor};
+//! use std::path::Path;
+//!
+//! # fn main() -> Result<(), dipse::error::Error> {
+//! let dir = Path::new(".");
+//! let set = ConfigSet::discover(dir)?;
+//! let resolution = Resolver::new(&set).resolve(dir)?;
+//! let mut executor = ShellExecutor::new();
+//! for cmd in resolution.plan(&["build".to_string()])? {
+//!     executor.execute(&cmd)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
 
 /// StructOpt and argument parsing
 pub mod args;
+/// Dynamic shell completion
+pub mod complete;
 /// Find, create and read config files
 pub mod config;
+/// Aliases from package.json, Makefiles, justfiles and cargo aliases
+pub mod discover;
+/// Format preserving changes to config files
+pub mod edit;
+/// Open config files in an editor
+pub mod editor;
 /// Errors
 pub mod error;
+/// Run commands
+pub mod executor;
+/// Render aliases for other tools
+pub mod export;
+/// Journal of alias changes
+pub mod history;
+/// Import aliases from other tools
+pub mod import;
+/// Output of `dipse list`
+pub mod list;
+/// Typed config model
+pub mod model;
 /// Parse config files
 pub mod parser;
+/// Interactive alias picker
+pub mod picker;
+/// Match config sections to directories
+pub mod resolver;
+/// Shell integration
+pub mod shell;
+/// Suggestions for mistyped alias names
+pub mod suggest;
+/// Allowlist of project config files that may run aliases
+pub mod trust;
 /// Utility methods
 pub mod utils;
 
+pub use crate::{
+    config::ConfigSet,
+    executor::{Executor, RecordingExecutor, ShellExecutor},
+    resolver::Resolver,
+};
+
 use crate::{
     args::{Crud, Opt, SubOpt},
-    config::get_config_path,
-    parser::{parse_toml, Entry},
+    config::{get_config_path, init_config},
+    model::Config,
+    resolver::Resolution,
     utils::CommandParams,
 };
 use error::Error;
 use std::{
-    fs::{self, File},
-    io::Write,
-    path::PathBuf,
-    process::Output,
-    vec,
+    env,
+    io::{self, IsTerminal, Write},
 };
-use structopt::StructOpt;
-use utils::{exec_command, get_current_dir};
-
-/// Get a tuple with config path and the config str
-fn get_config_path_and_str(config_path: Option<PathBuf>) -> Result<(PathBuf, String), Error> {
-    let config_path = match config_path {
-        Some(c) => Some(c),
-        None => get_config_path()?,
-    };
-
-    match config_path {
-        Some(e) => {
-            let config_str = match fs::read_to_string(&e) {
-                Err(a) => return Err(Error::NoFile(e, a)),
-                Ok(s) => s,
-            };
-
-            Ok((e, config_str))
-        }
-        None => Err(Error::NoConfigFile),
-    }
-}
-
-/// Executor
-pub fn run() -> Result<(), Error> {
-    let opt = Opt::from_args();
+use utils::get_current_dir;
 
+/// Command line entry point, output of subcommands is written to `out`
+pub fn run(opt: Opt, out: &mut dyn Write) -> Result<(), Error> {
     let config_path = opt.config_path;
     let debug = opt.debug;
-
-    if let Some(sub_cmd) = opt.sub_cmd {
-        match sub_cmd {
-            SubOpt::Init => {
-                let config_path = match config_path {
-                    Some(c) => Some(c),
-                    None => get_config_path()?,
-                };
-                match config_path {
-                    Some(c) => return Err(Error::ConfigExist(c)),
-                    None => {
-                        let curr_dir = get_current_dir()?;
-
-                        let new_p = curr_dir.join(".d.toml");
-                        let mut f = match File::create(&new_p) {
-                            Err(e) => return Err(Error::ConfigFileCreation(new_p, e)),
-                            Ok(e) => e,
-                        };
-
-                        if let Err(e) = writeln!(f, "[\"{}\"]", curr_dir.display()) {
-                            return Err(Error::ConfigFileWrite(new_p, e));
-                        }
-
-                        if let Err(e) = f.flush() {
-                            return Err(Error::ConfigFileWrite(new_p, e));
-                        }
-                    }
-                };
-            }
-            SubOpt::Edit => {
-                let (config_path, _config_str) = get_config_path_and_str(config_path)?;
-                start_editor(config_path)?;
+    // Nothing runs with `-n`, so there is nothing to confirm
+    let yes = opt.yes
+        || opt.no_op
+        || env::var("DIPSE_ASSUME_YES").is_ok_and(|v| !v.is_empty() && v != "0");
+
+    let pwd = get_current_dir()?;
+
+    let sub_cmd = match opt.sub_cmd {
+        Some(s) => s,
+        None => {
+            let set = ConfigSet::load(config_path, &pwd)?;
+            let resolution = Resolver::new(&set).resolve(&pwd)?;
+            resolution.print_warnings();
+
+            if !(io::stdin().is_terminal() && io::stdout().is_terminal()) {
+                let text = list::merged(&resolution, None, list::Options::default())?;
+                if let Err(e) = write!(out, "{}", text) {
+                    return Err(Error::Output(e));
+                }
+                return Ok(());
             }
-            SubOpt::Crud(crud) => {
-                let (config_path, config_str) = get_config_path_and_str(config_path)?;
-
-                let pwd = get_current_dir()?;
-
-                let entries = parse_toml(&config_str)?;
-
-                let mut c = config_path.clone();
-                c.pop();
-
-                let mut this_dir = vec![];
-                for entry in &entries {
-                    let entry_path = c.join(entry.0).canonicalize().expect("asdf");
+            resolution.best()?;
+            let pick = match picker::pick(&resolution)? {
+                Some(p) => p,
+                None => return Ok(()),
+            };
 
-                    // Check if the entry path is IN the pwd
-                    // This allows dipse to work when inside a nested system
-                    if pwd.starts_with(entry_path) {
-                        this_dir.push(entry)
-                    }
-                }
+            let mut cmd_list = vec![pick.name, "--".to_string()];
+            cmd_list.extend(pick.args);
+            let mut executor = executor(opt.no_op);
+            return run_cmd(out, executor.as_mut(), &resolution, &cmd_list, debug, yes);
+        }
+    };
 
-                if this_dir.is_empty() {
-                    return Err(Error::NoConfigForPath(pwd));
+    match sub_cmd {
+        SubOpt::Init => {
+            let config_path = match config_path {
+                Some(c) => Some(c),
+                None => get_config_path()?,
+            };
+            match config_path {
+                Some(c) => return Err(Error::ConfigExist(c)),
+                None => {
+                    let path = init_config(&pwd)?;
+                    let config = Config::load(path.clone())?;
+                    trust::allow(&path, &config.text)?;
                 }
-
-                this_dir.sort_by(|a, b| b.0.cmp(a.0));
-
-                let (_path, _entry) = this_dir[0];
-
-                // All entries in the file
-                let mut new_entries = entries.clone();
-
-                // Entry for the current path
-                let entry = new_entries.get_mut(_path).unwrap();
-
-                match crud {
-                    Crud::List { name } => list_entries(entry, name)?,
-                    Crud::Add { name, cmd } => {
-                        if let Some(c) = entry.get(&name) {
-                            return Err(Error::CmdStringExists(get_current_dir()?, c.to_string()));
-                        }
-                        entry.insert(name, cmd);
-                    }
-                    Crud::Delete { name } => {
-                        if entry.get(&name).is_none() {
-                            return Err(Error::NoCmdStringFound(get_current_dir()?, name));
-                        }
-                        entry.remove(&name);
-                    }
-                    Crud::Update { name, cmd } => {
-                        if entry.get(&name).is_none() {
-                            return Err(Error::NoCmdStringFound(get_current_dir()?, name));
-                        }
-                        entry.insert(name, cmd);
+            };
+        }
+        SubOpt::Edit => {
+            let (path, line) = editor::target(config_path, &pwd)?;
+            editor::edit(out, &path, line)?;
+        }
+        SubOpt::Crud(Crud::List {
+            name,
+            json,
+            toml,
+            all,
+            origin,
+            declared,
+        }) => {
+            let set = ConfigSet::load(config_path, &pwd)?;
+            let resolution = Resolver::new(&set).resolve(&pwd)?;
+            resolution.print_warnings();
+
+            let options = list::Options {
+                format: match (json, toml) {
+                    (true, _) => list::Format::Json,
+                    (_, true) => list::Format::Toml,
+                    _ => list::Format::Table,
+                },
+                origin,
+                declared,
+                color: list::use_color(
+                    io::stdout().is_terminal(),
+                    env::var_os("NO_COLOR").as_deref(),
+                ),
+            };
+            let text = if all {
+                list::all(&set, &resolution, options)?
+            } else {
+                list::merged(&resolution, name.as_deref(), options)?
+            };
+            if let Err(e) = write!(out, "{}", text) {
+                return Err(Error::Output(e));
+            }
+        }
+        SubOpt::Crud(Crud::Add {
+            name,
+            cmd,
+            section,
+            global,
+            create_section,
+        }) => {
+            let section = section.as_deref();
+            config::add_alias(
+                config_path,
+                &pwd,
+                &name,
+                &cmd,
+                section,
+                global,
+                create_section,
+            )?
+        }
+        SubOpt::Crud(Crud::Copy { name, to, .. }) => {
+            let msg = config::transfer_alias(config_path, &pwd, &name, to, false)?;
+            print_lines(out, &[msg])?;
+        }
+        SubOpt::Crud(Crud::Move { name, to, .. }) => {
+            let msg = config::transfer_alias(config_path, &pwd, &name, to, true)?;
+            print_lines(out, &[msg])?;
+        }
+        SubOpt::Crud(Crud::Update { name, cmd }) => {
+            config::update_alias(config_path, &pwd, &name, cmd)?
+        }
+        SubOpt::Crud(Crud::Delete { name }) => config::delete_alias(config_path, &pwd, &name)?,
+        SubOpt::Crud(Crud::Rename { name, new_name }) => {
+            config::rename_alias(config_path, &pwd, &name, &new_name)?
+        }
+        SubOpt::Import {
+            from,
+            path,
+            on_conflict,
+            prefix,
+        } => {
+            let lines = import::import(config_path, &pwd, from, path, on_conflict, prefix)?;
+            print_lines(out, &lines)?;
+        }
+        SubOpt::Allow => {
+            let set = ConfigSet::load(config_path, &pwd)?;
+            for config in &set.files {
+                let line = match trust::is_allowed(&config.path, &config.text) {
+                    true => format!("{} is allowed already", config.path.display()),
+                    false => {
+                        trust::allow(&config.path, &config.text)?;
+                        format!("allowed {}", config.path.display())
                     }
-                }
-
-                let new_config_str = match toml::to_string_pretty(&new_entries) {
-                    Err(e) => return Err(Error::UnableToSerialize(e)),
-                    Ok(s) => s,
                 };
-                if let Err(e) = fs::write(&config_path, new_config_str) {
-                    return Err(Error::ConfigFileWrite(config_path, e));
+                if let Err(e) = writeln!(out, "{}", line) {
+                    return Err(Error::Output(e));
                 }
             }
-            SubOpt::Other(cmd) => {
-                let (mut config_path, config_str) = get_config_path_and_str(config_path)?;
-
-                let pwd = get_current_dir()?;
-
-                let entries = parse_toml(&config_str)?;
-
-                config_path.pop();
-
-                let mut this_dir = vec![];
-                for entry in &entries {
-                    let entry_path = config_path.join(entry.0);
-                    //println!("{:?}", entry_path);
-                    //let a  = entry_path.canonicalize().expect("a");
+        }
+        SubOpt::History { all } => {
+            let lines = history::listing(config_path, &pwd, all)?;
+            print_lines(out, &lines)?;
+        }
+        SubOpt::Undo { n } => {
+            let lines = history::undo(config_path, &pwd, n)?;
+            print_lines(out, &lines)?;
+        }
+        SubOpt::Gc { remove } => {
+            let lines = config::gc_sections(config_path, &pwd, remove)?;
+            print_lines(out, &lines)?;
+        }
+        SubOpt::Relocate { old, new, dry_run } => {
+            let lines = config::relocate_sections(config_path, &pwd, &old, &new, dry_run)?;
+            print_lines(out, &lines)?;
+        }
+        SubOpt::Export { format } => {
+            let set = ConfigSet::load(config_path, &pwd)?;
+            let resolution = Resolver::new(&set).resolve(&pwd)?;
+            resolution.print_warnings();
+            resolution.best()?;
+
+            let aliases = resolution.aliases().into_values().collect::<Vec<_>>();
+            let (text, skipped) = export::render(format, &aliases);
+            for reason in skipped {
+                eprintln!("warning: skipped {}", reason);
+            }
+            if let Err(e) = write!(out, "{}", text) {
+                return Err(Error::Output(e));
+            }
+        }
+        SubOpt::ShellInit { shell } => {
+            if let Err(e) = write!(out, "{}", shell::init_script(shell)) {
+                return Err(Error::Output(e));
+            }
+        }
+        SubOpt::Hook { shell, from, funcs } => {
+            let code = shell::hook_for(shell, config_path, &pwd, from, &funcs)?;
+            if let Err(e) = write!(out, "{}", code) {
+                return Err(Error::Output(e));
+            }
+        }
+        SubOpt::Completions { shell } => {
+            if let Err(e) = write!(out, "{}", complete::completion_script(shell)) {
+                return Err(Error::Output(e));
+            }
+        }
+        SubOpt::Complete { words } => {
+            let line = complete::Line::parse(&words);
+            let set = ConfigSet::load(line.config_path.clone().or(config_path), &pwd).ok();
+            let resolution = match &set {
+                Some(set) => Resolver::new(set).resolve(&pwd).ok(),
+                None => None,
+            };
 
-                    // Check if the entry path is IN the pwd
-                    // This allows dipse to work when inside a nested system
-                    if pwd.starts_with(entry_path) {
-                        this_dir.push(entry)
-                    }
+            for (name, description) in complete::candidates(&line, resolution.as_ref()) {
+                if let Err(e) = writeln!(out, "{}\t{}", name, description) {
+                    return Err(Error::Output(e));
                 }
+            }
+        }
+        SubOpt::Which { name, args } => {
+            let set = ConfigSet::load(config_path, &pwd)?;
+            let resolution = Resolver::new(&set).resolve(&pwd)?;
+            resolution.print_warnings();
 
-                if this_dir.is_empty() {
-                    return Err(Error::NoConfigForPath(pwd));
-                }
+            print_lines(out, &resolution.explain(&name, args)?)?
+        }
+        SubOpt::Other(cmd) => {
+            let set = ConfigSet::load(config_path, &pwd)?;
+            let resolution = Resolver::new(&set).resolve(&pwd)?;
+            resolution.print_warnings();
 
-                this_dir.sort_by(|a, b| b.0.cmp(a.0));
+            let mut executor = executor(opt.no_op);
+            run_cmd(out, executor.as_mut(), &resolution, &cmd, debug, yes)?
+        }
+    }
 
-                let (_path, entry) = this_dir[0];
+    Ok(())
+}
 
-                run_cmd(cmd, entry, debug, opt.no_op)?
-            }
+/// Write `lines` to `out`, one per line
+/// Error:
+/// - The output could not be written
+fn print_lines(out: &mut dyn Write, lines: &[String]) -> Result<(), Error> {
+    for line in lines {
+        if let Err(e) = writeln!(out, "{}", line) {
+            return Err(Error::Output(e));
         }
     }
+    Ok(())
+}
+
+/// Executor for aliases, one that only records with `-n`
+fn executor(no_op: bool) -> Box<dyn Executor> {
+    if no_op {
+        Box::new(RecordingExecutor::default())
+    } else {
+        Box::new(ShellExecutor::new())
+    }
+}
+
+/// Ask `question` before running `cmd`, without a terminal the answer is no
+/// Error:
+/// - The user said no, or there is no terminal
+fn confirm(cmd: &CommandParams, question: &str) -> Result<(), Error> {
+    if !(io::stdin().is_terminal() && io::stderr().is_terminal()) {
+        return Err(Error::NotConfirmed(cmd.to_string()));
+    }
+    let answer = dialoguer::Confirm::new()
+        .with_prompt(question)
+        .default(false)
+        .interact();
+    match answer {
+        Err(dialoguer::Error::IO(e)) => Err(Error::Prompt(e)),
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Error::Declined(cmd.to_string())),
+    }
+}
 
+/// Refuse to run the aliases of `cmd_list` if they come from a config file that is not allowed
+///
+/// The fallback of a section counts as defined in its file. Names that resolve to nothing are
+/// left to [`Resolution::plan`] to report.
+/// Error:
+/// - A config file is new or changed since `dipse allow`
+fn check_allowed(resolution: &Resolution, cmd_list: &[String]) -> Result<(), Error> {
+    for name in cmd_list.iter().take_while(|n| *n != "--") {
+        let candidate = match resolution.get(name) {
+            Ok((c, _)) => c,
+            Err(Error::NoCmdStringFound(..)) => match resolution.fallback() {
+                Some(c) => c,
+                None => continue,
+            },
+            Err(e) => return Err(e),
+        };
+        trust::check(candidate.config)?;
+    }
     Ok(())
 }
 
-/// Run the specified commands defined in entry
+/// Run the specified commands defined for the current directory
 ///
-/// If a command requires arguments, then last command will get the arguments
+/// If a command requires arguments, then last command will get the arguments. Stops at the
+/// first command that fails. Commands that ask for confirmation are confirmed before anything
+/// runs, unless `yes` is set.
 ///
 /// dipse build run -- args
-fn run_cmd(cmd_list: Vec<String>, entry: &Entry, debug: bool, no_op: bool) -> Result<(), Error> {
-    let index_of_splitter = cmd_list.iter().position(|s| s == "--");
-
-    let cmd_params = match index_of_splitter {
-        Some(idx) => {
-            // THese are cmds without args
-            let cmd = &cmd_list[..idx - 1];
-
-            let mut cmd_params = cmd
-                .iter()
-                .map(|c| CommandParams {
-                    cmd_str: c.to_string(),
-                    params: vec![],
-                })
-                .collect::<Vec<CommandParams>>();
-
-            let arg_cmd = &cmd_list[idx - 1];
-            let args = &cmd_list[idx + 1..];
-
-            cmd_params.push(CommandParams {
-                cmd_str: arg_cmd.to_string(),
-                params: args.into(),
-            });
-
-            cmd_params
+fn run_cmd(
+    out: &mut dyn Write,
+    executor: &mut dyn Executor,
+    resolution: &Resolution,
+    cmd_list: &[String],
+    debug: bool,
+    yes: bool,
+) -> Result<(), Error> {
+    check_allowed(resolution, cmd_list)?;
+    let cmds = resolution.plan(cmd_list)?;
+    for cmd in &cmds {
+        if let Some(question) = cmd.confirm.as_ref().filter(|_| !yes) {
+            confirm(cmd, question)?;
         }
-        None => cmd_list
-            .iter()
-            .map(|c| CommandParams {
-                cmd_str: c.to_string(),
-                params: vec![],
-            })
-            .collect::<Vec<CommandParams>>(),
-    };
-
-    for mut cmd in cmd_params {
-        let cmd_str = get_cmd_str(entry, &cmd.cmd_str)?;
-        cmd.cmd_str = cmd_str.clone();
+    }
+    for cmd in cmds {
         if debug {
-            println!("`{}`", cmd);
+            if let Err(e) = writeln!(out, "`{}`", cmd).and_then(|_| out.flush()) {
+                return Err(Error::Output(e));
+            }
         }
-        if no_op {
-            continue;
+        let code = executor.execute(&cmd)?;
+        if code != 0 {
+            return Err(Error::ChildFailed(cmd.to_string(), code));
         }
-        exec_command(cmd)?;
     }
     Ok(())
 }
 
-fn start_editor(config_path: PathBuf) -> Result<Output, Error> {
-    exec_command(CommandParams {
-        cmd_str: format!("$EDITOR {}", config_path.display()),
-        params: vec![],
-    })
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::get_global_config_path, parser::parse_config, resolver::Candidate};
+    use std::path::{Path, PathBuf};
 
-/// List everything in the entry
-/// Maybe can be moved to a Display trait
-fn list_entries(entry: &Entry, name: Option<String>) -> Result<(), Error> {
-    if let Some(name) = name {
-        match entry.get(&name) {
-            None => return Err(Error::NoCmdStringFound(get_current_dir()?, name)),
-            Some(cmd) => {
-                println!("{}: {}", name, cmd);
-            }
+    /// Fails every command whose text is `fail`, records the others
+    #[derive(Default)]
+    struct FailingExecutor {
+        recorder: RecordingExecutor,
+    }
+
+    impl Executor for FailingExecutor {
+        fn execute(&mut self, cmd_params: &CommandParams) -> Result<i32, Error> {
+            self.recorder.execute(cmd_params)?;
+            Ok(if cmd_params.cmd_str == "fail" { 3 } else { 0 })
         }
-    } else {
-        println!("{:#?}", entry);
     }
-    Ok(())
-}
 
-/// Get command string for alias from a entry
-fn get_cmd_str(entry: &Entry, cmd: &str) -> Result<String, Error> {
-    match entry.get(cmd) {
-        Some(s) => Ok(s.to_owned()),
-        None => Err(Error::NoCmdStringFound(get_current_dir()?, cmd.to_string())),
+    /// The global config with a `"/"` section of `aliases`, it needs no `dipse allow`
+    fn config(aliases: &str) -> Config {
+        let path = get_global_config_path().unwrap();
+        parse_config(&path, &format!("[\"/\"]\n{}", aliases)).unwrap()
+    }
+
+    fn resolution(config: &Config) -> Resolution<'_> {
+        Resolution {
+            pwd: PathBuf::from("/"),
+            candidates: vec![Candidate {
+                config,
+                section: &config.sections[0],
+                path: PathBuf::from("/"),
+                discovered: vec![],
+            }],
+            warnings: vec![],
+        }
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn run_cmd_runs_every_alias_in_order() {
+        let config = config("b = \"make\"\nt = \"make test\"\n");
+        let mut executor = RecordingExecutor::default();
+        let mut out = vec![];
+        let cmd_list = args(&["b", "t", "--", "-v"]);
+        run_cmd(
+            &mut out,
+            &mut executor,
+            &resolution(&config),
+            &cmd_list,
+            true,
+            false,
+        )
+        .unwrap();
+
+        let run = executor
+            .commands
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(run, ["make", "make test -v"]);
+        assert_eq!(String::from_utf8(out).unwrap(), "`make`\n`make test -v`\n");
+    }
+
+    #[test]
+    fn run_cmd_stops_at_the_first_failure() {
+        let config = config("f = \"fail\"\nb = \"make\"\n");
+        let mut executor = FailingExecutor::default();
+        let cmd_list = args(&["b", "f", "b"]);
+        let res = run_cmd(
+            &mut vec![],
+            &mut executor,
+            &resolution(&config),
+            &cmd_list,
+            false,
+            false,
+        );
+
+        assert!(matches!(res, Err(Error::ChildFailed(cmd, 3)) if cmd == "fail"));
+        assert_eq!(executor.recorder.commands.len(), 2);
+    }
+
+    #[test]
+    fn run_cmd_runs_nothing_if_an_alias_is_missing() {
+        let config = config("b = \"make\"\n");
+        let mut executor = RecordingExecutor::default();
+        let cmd_list = args(&["b", "nope"]);
+        let res = run_cmd(
+            &mut vec![],
+            &mut executor,
+            &resolution(&config),
+            &cmd_list,
+            false,
+            false,
+        );
+
+        assert!(matches!(res, Err(Error::NoCmdStringFound(..))));
+        assert!(executor.commands.is_empty());
+    }
+
+    #[test]
+    fn run_cmd_refuses_files_that_are_not_allowed() {
+        let path = Path::new("/nonexistent/dipse-test/.d.toml");
+        let config = parse_config(path, "[\"/\"]\nb = \"make\"\n").unwrap();
+        let mut executor = RecordingExecutor::default();
+        let cmd_list = args(&["b"]);
+        let res = run_cmd(
+            &mut vec![],
+            &mut executor,
+            &resolution(&config),
+            &cmd_list,
+            false,
+            true,
+        );
+
+        assert!(matches!(res, Err(Error::NotAllowed(..))));
+        assert!(executor.commands.is_empty());
+    }
+
+    #[test]
+    fn run_cmd_skips_confirmation_with_yes() {
+        let config = config("d = { cmd = \"deploy\", confirm = \"Really?\" }\n");
+        let mut executor = RecordingExecutor::default();
+        let cmd_list = args(&["d"]);
+        run_cmd(
+            &mut vec![],
+            &mut executor,
+            &resolution(&config),
+            &cmd_list,
+            false,
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(executor.commands[0].confirm.as_deref(), Some("Really?"));
     }
 }
@@ -0,0 +1,471 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Output of `dipse list`
+
+use crate::{
+    config::ConfigSet,
+    error::Error,
+    model::{Alias, Config, Section},
+    resolver::{Resolution, Warning},
+};
+use serde_json::{json, Value};
+use std::{ffi::OsStr, fmt::Write, path::Path};
+
+/// How aliases are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Aligned columns for people
+    #[default]
+    Table,
+    /// JSON array, one object per alias
+    Json,
+    /// TOML like the config file
+    Toml,
+}
+
+/// Options of `dipse list`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Output format
+    pub format: Format,
+    /// Add where each alias comes from and what it shadows
+    pub origin: bool,
+    /// Keep declaration order instead of sorting by name
+    pub declared: bool,
+    /// Use ANSI colours in tables
+    pub color: bool,
+}
+
+/// Whether tables get colours: only on a terminal, and never when `NO_COLOR` is set to
+/// something, see <https://no-color.org>
+pub fn use_color(is_terminal: bool, no_color: Option<&OsStr>) -> bool {
+    is_terminal && no_color.is_none_or(|v| v.is_empty())
+}
+
+/// An alias as listed
+pub struct Row<'a> {
+    /// The alias itself
+    pub alias: &'a Alias,
+    /// Key of the section the alias is taken from
+    pub key: &'a Path,
+    /// Aliases with the same name in the sections around, hidden by this one
+    pub shadows: Vec<&'a Alias>,
+}
+
+/// Aliases available in the directory of `resolution`
+///
+/// In declaration order, the aliases of the most specific section first.
+pub fn rows<'a>(resolution: &'a Resolution) -> Vec<Row<'a>> {
+    let mut rows: Vec<Row> = vec![];
+    for candidate in &resolution.candidates {
+        for alias in candidate.aliases() {
+            match rows.iter_mut().find(|r| r.alias.name == alias.name) {
+                Some(row) => row.shadows.push(alias),
+                None => rows.push(Row {
+                    alias,
+                    key: &candidate.section.key,
+                    shadows: vec![],
+                }),
+            }
+        }
+    }
+    rows
+}
+
+/// Aliases written in `section`
+fn section_rows(section: &Section) -> Vec<Row<'_>> {
+    section
+        .aliases
+        .iter()
+        .map(|alias| Row {
+            alias,
+            key: &section.key,
+            shadows: vec![],
+        })
+        .collect()
+}
+
+/// List the aliases of the directory of `resolution`, or only the alias `name`
+/// Error:
+/// - No section applies to the directory
+/// - `name` is not defined
+/// - Could not serialize TOML
+pub fn merged(
+    resolution: &Resolution,
+    name: Option<&str>,
+    options: Options,
+) -> Result<String, Error> {
+    resolution.best()?;
+    let mut rows = rows(resolution);
+    if let Some(name) = name {
+        let (_, alias) = resolution.get(name)?;
+        rows.retain(|r| r.alias.name == alias.name);
+    }
+    if !options.declared {
+        rows.sort_by(|a, b| a.alias.name.cmp(&b.alias.name));
+    }
+
+    match options.format {
+        Format::Table => Ok(table(&rows, options, "")),
+        Format::Json => Ok(json_string(&Value::Array(
+            rows.iter().map(json_row).collect(),
+        ))),
+        Format::Toml => {
+            let mut section = Section::new(&resolution.pwd);
+            section.aliases = rows.iter().map(|r| r.alias.clone()).collect();
+            Config {
+                path: resolution.pwd.clone(),
+                sections: vec![section],
+                ..Config::default()
+            }
+            .to_toml()
+        }
+    }
+}
+
+/// List every section of every config file in `set`
+/// Error:
+/// - Could not serialize TOML
+pub fn all(set: &ConfigSet, resolution: &Resolution, options: Options) -> Result<String, Error> {
+    let applies = |config: &Config, section: &Section| {
+        resolution
+            .candidates
+            .iter()
+            .any(|c| c.config.path == config.path && c.section.key == section.key)
+    };
+    let stale = |config: &Config, section: &Section| {
+        resolution.warnings.iter().any(|w| match w {
+            Warning::StaleSection(c, k, _) => *c == config.path && *k == section.key,
+            _ => false,
+        })
+    };
+
+    let mut out = String::new();
+    match options.format {
+        Format::Table => {
+            let paint = Paint(options.color);
+            for config in &set.files {
+                let _ = writeln!(out, "{}", paint.bold(&config.path.display().to_string()));
+                for section in &config.sections {
+                    let mut rows = section_rows(section);
+                    if !options.declared {
+                        rows.sort_by(|a, b| a.alias.name.cmp(&b.alias.name));
+                    }
+                    let mark = if applies(config, section) {
+                        "  (applies here)"
+                    } else if stale(config, section) {
+                        "  (directory is missing)"
+                    } else {
+                        ""
+                    };
+                    let _ = writeln!(
+                        out,
+                        "  {}{}",
+                        paint.green(&format!("[{:?}]", section.key)),
+                        paint.dim(mark)
+                    );
+                    if rows.is_empty() {
+                        let _ = writeln!(out, "    {}", paint.dim("no aliases"));
+                    } else {
+                        out.push_str(&table(&rows, options, "    "));
+                    }
+                }
+            }
+        }
+        Format::Json => {
+            let mut sections = vec![];
+            for config in &set.files {
+                for section in &config.sections {
+                    sections.push(json!({
+                        "config": config.path,
+                        "section": section.key,
+                        "current": applies(config, section),
+                        "stale": stale(config, section),
+                        "aliases": section_rows(section).iter().map(json_row).collect::<Vec<_>>(),
+                    }));
+                }
+            }
+            out = json_string(&Value::Array(sections));
+        }
+        Format::Toml => {
+            for config in &set.files {
+                let _ = writeln!(out, "# {}\n{}", config.path.display(), config.to_toml()?);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// ANSI colours, if enabled
+struct Paint(bool);
+
+impl Paint {
+    fn paint(&self, code: &str, s: &str) -> String {
+        if self.0 && !s.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", code, s)
+        } else {
+            s.to_string()
+        }
+    }
+
+    fn bold(&self, s: &str) -> String {
+        self.paint("1", s)
+    }
+
+    fn green(&self, s: &str) -> String {
+        self.paint("32", s)
+    }
+
+    fn dim(&self, s: &str) -> String {
+        self.paint("2", s)
+    }
+}
+
+/// Widest column before text is left to overflow
+const MAX_WIDTH: usize = 40;
+
+/// Where `row` comes from and what it shadows
+fn origin(row: &Row) -> String {
+    let mut origin = if row.alias.discovered {
+        format!("{} (discovered)", row.alias.source)
+    } else {
+        format!("{} [{:?}]", row.alias.source, row.key)
+    };
+    if !row.shadows.is_empty() {
+        let shadows = row
+            .shadows
+            .iter()
+            .map(|a| a.source.to_string())
+            .collect::<Vec<_>>();
+        let _ = write!(origin, ", shadows {}", shadows.join(", "));
+    }
+    origin
+}
+
+/// Aligned name, command and description columns, each line starting with `indent`
+fn table(rows: &[Row], options: Options, indent: &str) -> String {
+    let paint = Paint(options.color);
+    let mut lines = vec![[
+        "NAME".to_string(),
+        "COMMAND".to_string(),
+        "DESCRIPTION".to_string(),
+        "ORIGIN".to_string(),
+    ]];
+    for row in rows {
+        let description = row.alias.description.as_deref().unwrap_or_default();
+        let mut command = row
+            .alias
+            .summary()
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        if row.alias.discovered {
+            let file = &row.alias.source.file;
+            let file = file.file_name().unwrap_or(file.as_os_str());
+            let _ = write!(command, " (from {})", file.to_string_lossy());
+        }
+        lines.push([
+            row.alias.name.clone(),
+            command,
+            description.lines().next().unwrap_or_default().to_string(),
+            origin(row),
+        ]);
+    }
+
+    let columns = if options.origin { 4 } else { 3 };
+    let mut widths = [0; 4];
+    for line in &lines {
+        for (i, cell) in line.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count().min(MAX_WIDTH));
+        }
+    }
+
+    let mut out = String::new();
+    for (n, line) in lines.iter().enumerate() {
+        let last = (0..columns)
+            .rev()
+            .find(|&i| !line[i].is_empty())
+            .unwrap_or(0);
+        let mut text = String::new();
+        for i in 0..=last {
+            let cell = if i < last {
+                format!("{:w$}  ", line[i], w = widths[i])
+            } else {
+                line[i].clone()
+            };
+            let cell = match (n, i) {
+                (0, _) => paint.bold(&cell),
+                (_, 0) => paint.green(&cell),
+                (_, 3) => paint.dim(&cell),
+                _ => cell,
+            };
+            text.push_str(&cell);
+        }
+        let _ = writeln!(out, "{}{}", indent, text);
+    }
+    out
+}
+
+/// JSON object for `row`
+fn json_row(row: &Row) -> Value {
+    let alias = row.alias;
+    json!({
+        "name": alias.name,
+        "cmd": match alias.script { Some(_) => None, None => Some(&alias.cmd) },
+        "script": alias.script,
+        "interpreter": alias.interpreter,
+        "description": alias.description,
+        "env": alias.env,
+        "confirm": alias.confirmation(),
+        "section": row.key,
+        "source": alias.source.to_string(),
+        "discovered": alias.discovered,
+        "shadows": row.shadows.iter().map(|a| a.source.to_string()).collect::<Vec<_>>(),
+    })
+}
+
+fn json_string(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::Source, parser::parse_config, resolver::Candidate};
+    use std::path::PathBuf;
+
+    fn config() -> Config {
+        let text = "[sub]\nbuild = { cmd = \"make\", description = \"Build it\" }\n[\".\"]\nbuild = \"cargo build\"\ntest = \"cargo test\"\n";
+        parse_config(Path::new("/project/.d.toml"), text).unwrap()
+    }
+
+    /// Resolution of `/project/sub`, with `discovered` found next to the outer section
+    fn resolution(config: &Config, discovered: Vec<Alias>) -> Resolution<'_> {
+        let mut discovered = Some(discovered);
+        Resolution {
+            pwd: PathBuf::from("/project/sub"),
+            candidates: config
+                .sections
+                .iter()
+                .map(|section| Candidate {
+                    config,
+                    section,
+                    path: Path::new("/project").join(&section.key),
+                    discovered: match section.key == Path::new(".") {
+                        true => discovered.take().unwrap_or_default(),
+                        false => vec![],
+                    },
+                })
+                .collect(),
+            warnings: vec![],
+        }
+    }
+
+    fn discovered() -> Alias {
+        let mut alias = Alias::new("lint", "make lint");
+        alias.source = Source::new(Path::new("/project/Makefile"), 3);
+        alias.discovered = true;
+        alias
+    }
+
+    #[test]
+    fn table_lists_the_nearest_aliases() {
+        let config = config();
+        let text = merged(&resolution(&config, vec![]), None, Options::default()).unwrap();
+        assert_eq!(
+            text,
+            "NAME   COMMAND     DESCRIPTION\n\
+             build  make        Build it\n\
+             test   cargo test\n"
+        );
+    }
+
+    #[test]
+    fn table_marks_discovered_aliases_and_origins() {
+        let config = config();
+        let resolution = resolution(&config, vec![discovered()]);
+        let options = Options {
+            origin: true,
+            ..Options::default()
+        };
+        let text = merged(&resolution, None, options).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert!(lines[0].ends_with("ORIGIN"));
+        assert!(lines[1].contains("[\"sub\"], shadows /project/.d.toml:4"));
+        assert!(lines[2].starts_with("lint   make lint (from Makefile)"));
+        assert!(lines[2].ends_with("/project/Makefile:3 (discovered)"));
+
+        let text = merged(&resolution, None, Options::default()).unwrap();
+        assert!(!text.contains("ORIGIN"));
+        assert!(!text.contains("shadows"));
+    }
+
+    #[test]
+    fn json_and_toml_hold_the_same_aliases() {
+        let config = config();
+        let resolution = resolution(&config, vec![discovered()]);
+        let json = Options {
+            format: Format::Json,
+            ..Options::default()
+        };
+        let value: Value = serde_json::from_str(&merged(&resolution, None, json).unwrap()).unwrap();
+        let names = value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["build", "lint", "test"]);
+        assert_eq!(value[0]["section"], "sub");
+        assert_eq!(value[0]["shadows"], json!(["/project/.d.toml:4"]));
+        assert_eq!(value[1]["discovered"], true);
+
+        let toml = Options {
+            format: Format::Toml,
+            ..Options::default()
+        };
+        let text = merged(&resolution, Some("build"), toml).unwrap();
+        let listed = parse_config(Path::new("/project/sub/.d.toml"), &text).unwrap();
+        assert_eq!(listed.sections.len(), 1);
+        assert_eq!(listed.sections[0].aliases.len(), 1);
+        assert_eq!(listed.sections[0].aliases[0].cmd, "make");
+    }
+
+    #[test]
+    fn colours_only_on_terminals_without_no_color() {
+        assert!(use_color(true, None));
+        assert!(use_color(true, Some(OsStr::new(""))));
+        assert!(!use_color(true, Some(OsStr::new("1"))));
+        assert!(!use_color(false, None));
+
+        let config = config();
+        let resolution = resolution(&config, vec![]);
+        let color = Options {
+            color: true,
+            ..Options::default()
+        };
+        assert!(merged(&resolution, None, color)
+            .unwrap()
+            .contains("\x1b[32mbuild"));
+        assert!(!merged(&resolution, None, Options::default())
+            .unwrap()
+            .contains('\x1b'));
+    }
+}
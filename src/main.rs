@@ -16,13 +16,22 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::process::exit;
+use std::{error::Error, io, process::exit};
 
-use dipse::run;
+use dipse::{args::Opt, run};
+use structopt::StructOpt;
 
 fn main() {
-    if let Err(e) = run() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(opt, &mut io::stdout()) {
         eprintln!("{}", e);
-        exit(1)
+
+        let mut source = e.source();
+        while let Some(cause) = source {
+            eprintln!("  caused by: {}", cause);
+            source = cause.source();
+        }
+
+        exit(e.exit_code())
     }
 }
@@ -0,0 +1,845 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{
+    config::{backup, write_atomic},
+    discover::Tool,
+    edit,
+    error::Error,
+    parser::parse_config,
+    suggest::suggestions,
+    trust,
+    utils::{CommandParams, Launch},
+};
+use serde::{
+    de::{self, Deserializer},
+    ser::{SerializeMap, Serializer},
+    Deserialize, Serialize,
+};
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use toml_edit::DocumentMut;
+
+/// Location something was defined at
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Source {
+    /// File it was read from, empty if it was created in memory
+    pub file: PathBuf,
+    /// 1-based line in the file, 0 if unknown
+    pub line: usize,
+}
+
+impl Source {
+    /// Location in `file` at `line`
+    pub fn new(file: &Path, line: usize) -> Self {
+        Source {
+            file: file.to_path_buf(),
+            line,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.file.display())
+        } else {
+            write!(f, "{}:{}", self.file.display(), self.line)
+        }
+    }
+}
+
+/// `confirm` of an alias table, asking before the alias is run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Confirm {
+    /// `confirm = true` asks a generic question, `false` does not ask
+    Ask(bool),
+    /// `confirm = "question"` asks the question
+    Message(String),
+}
+
+/// A name that maps to a command
+///
+/// Written either as `name = "cmd"` or as a table
+/// `name = { cmd = "cmd", description = "...", env = { KEY = "value" } }`. Instead of `cmd`, a
+/// table can have a multi-line `script` with an `interpreter`. `confirm` makes `dipse` ask
+/// before running it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Alias {
+    /// Name used on the command line
+    pub name: String,
+    /// Command to run through the shell
+    pub cmd: String,
+    /// Short description for listings
+    pub description: Option<String>,
+    /// Extra environment variables for the command
+    pub env: BTreeMap<String, String>,
+    /// Where the alias was defined
+    pub source: Source,
+    /// Found in a file of the project's tools instead of the config, see [`crate::discover`]
+    pub discovered: bool,
+    /// Executable run directly instead of `cmd`, the arguments become its argv
+    pub file: Option<PathBuf>,
+    /// Inline script run instead of `cmd`, see [`Alias::interpreter`]
+    pub script: Option<String>,
+    /// Program running [`Alias::script`], like `python3` or `#!/usr/bin/env node`
+    ///
+    /// Without one, a script starting with a shebang is executed directly and any other script
+    /// is run with `sh`.
+    pub interpreter: Option<String>,
+    /// Ask before running the alias, see [`Alias::confirmation`]
+    pub confirm: Option<Confirm>,
+}
+
+impl Alias {
+    /// Alias with only a command
+    pub fn new(name: &str, cmd: &str) -> Self {
+        Alias {
+            name: name.to_string(),
+            cmd: cmd.to_string(),
+            ..Alias::default()
+        }
+    }
+
+    /// Command for listings, `<python3 script>` for inline scripts
+    pub fn summary(&self) -> String {
+        match self.launch() {
+            Launch::Script {
+                interpreter: Some(i),
+                ..
+            } => format!("<{} script>", i),
+            Launch::Script {
+                interpreter: None, ..
+            } => "<script>".to_string(),
+            _ => self.cmd.clone(),
+        }
+    }
+
+    /// How the command of the alias is started
+    pub fn launch(&self) -> Launch {
+        if let Some(body) = &self.script {
+            let interpreter = match &self.interpreter {
+                Some(i) => Some(i.trim_start_matches("#!").trim().to_string()),
+                None if body.starts_with("#!") => None,
+                None => Some("sh".to_string()),
+            };
+            return Launch::Script {
+                interpreter,
+                body: body.clone(),
+            };
+        }
+        match &self.file {
+            Some(f) => Launch::File(f.clone()),
+            None => Launch::Shell,
+        }
+    }
+
+    /// Alias called `name` from its JSON value, a command string or a table like in the config
+    pub fn from_value(name: &str, value: serde_json::Value) -> Result<Alias, String> {
+        match value {
+            serde_json::Value::String(cmd) => Ok(Alias::new(name, &cmd)),
+            value => match serde_json::from_value::<AliasTable>(value) {
+                Err(e) => Err(e.to_string()),
+                Ok(t) => t.into_alias(name),
+            },
+        }
+    }
+
+    /// Question to ask before running the alias, if it has to be confirmed
+    pub fn confirmation(&self) -> Option<String> {
+        match &self.confirm {
+            Some(Confirm::Ask(true)) => Some(format!("Run {}?", self.name)),
+            Some(Confirm::Message(m)) => Some(m.clone()),
+            Some(Confirm::Ask(false)) | None => None,
+        }
+    }
+
+    /// Whether the alias is only a command and can be written as a string
+    pub fn is_plain(&self) -> bool {
+        self.description.is_none()
+            && self.env.is_empty()
+            && self.script.is_none()
+            && self.confirm.is_none()
+    }
+}
+
+/// A table of the config file, holding the aliases for a directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Section {
+    /// Path as written in the config file, relative to the config file
+    pub key: PathBuf,
+    /// Aliases in declaration order
+    pub aliases: Vec<Alias>,
+    /// `prefix_match = true` lets a unique prefix of an alias name select the alias
+    pub prefix_match: Option<bool>,
+    /// Command template for aliases that are not defined, see [`Section::fallback_cmd`]
+    pub fallback: Option<String>,
+    /// Tools to discover aliases from in the section directory
+    pub discover: Option<Vec<Tool>>,
+    /// Directory, relative to the section directory, whose executables become aliases
+    pub scripts_dir: Option<PathBuf>,
+    /// Command the shell hook runs in the shell when entering the section, see [`crate::shell`]
+    pub on_enter: Option<String>,
+    /// Command the shell hook runs in the shell when leaving the section
+    pub on_leave: Option<String>,
+    /// Where the section header is
+    pub source: Source,
+}
+
+impl Section {
+    /// Empty section for `key`
+    pub fn new(key: &Path) -> Self {
+        Section {
+            key: key.to_path_buf(),
+            ..Section::default()
+        }
+    }
+
+    /// Alias called `name`
+    pub fn get(&self, name: &str) -> Option<&Alias> {
+        self.aliases.iter().find(|a| a.name == name)
+    }
+
+    /// Mutable alias called `name`
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Alias> {
+        self.aliases.iter_mut().find(|a| a.name == name)
+    }
+
+    /// Add `alias`, replacing the alias with the same name. Returns the replaced alias
+    pub fn insert(&mut self, alias: Alias) -> Option<Alias> {
+        match self.get_mut(&alias.name) {
+            Some(a) => Some(std::mem::replace(a, alias)),
+            None => {
+                self.aliases.push(alias);
+                None
+            }
+        }
+    }
+
+    /// Command for an undefined alias built from [`Section::fallback`]
+    ///
+    /// `{alias}` is replaced with the name of the alias and `{args}` with the arguments. Without
+    /// `{args}` the arguments are appended, like for any other alias.
+    pub fn fallback_cmd(&self, name: &str, args: &[String]) -> Option<CommandParams> {
+        let template = self.fallback.as_ref()?;
+        let cmd_str = template.replace("{alias}", name);
+
+        if cmd_str.contains("{args}") {
+            return Some(CommandParams {
+                cmd_str: cmd_str.replace("{args}", &args.join(" ")),
+                ..CommandParams::default()
+            });
+        }
+        Some(CommandParams {
+            cmd_str,
+            params: args.to_vec(),
+            ..CommandParams::default()
+        })
+    }
+
+    /// Apply the setting `name`, `Ok(false)` if `name` is not a setting but an alias
+    fn set(&mut self, name: &str, value: &toml::Value) -> Result<bool, String> {
+        match name {
+            PREFIX_MATCH => match value.as_bool() {
+                Some(b) => self.prefix_match = Some(b),
+                None => return Err(format!("{} must be true or false", name)),
+            },
+            FALLBACK => match value.as_str() {
+                Some(f) => self.fallback = Some(f.to_string()),
+                None => return Err(format!("{} must be a command string", name)),
+            },
+            DISCOVER => match value {
+                toml::Value::Boolean(true) => self.discover = Some(Tool::ALL.to_vec()),
+                toml::Value::Boolean(false) => self.discover = Some(vec![]),
+                toml::Value::Array(tools) => {
+                    let mut discover = vec![];
+                    for tool in tools {
+                        match tool.as_str().map(|t| t.parse::<Tool>()) {
+                            Some(Ok(t)) => discover.push(t),
+                            Some(Err(e)) => return Err(format!("{}: {}", name, e)),
+                            None => return Err(format!("{} must list tool names", name)),
+                        }
+                    }
+                    self.discover = Some(discover);
+                }
+                _ => return Err(format!("{} must be true, false or a list of tools", name)),
+            },
+            SCRIPTS_DIR => match value.as_str() {
+                Some(d) => self.scripts_dir = Some(PathBuf::from(d)),
+                None => return Err(format!("{} must be a path", name)),
+            },
+            ON_ENTER | ON_LEAVE => match value.as_str() {
+                Some(c) if name == ON_ENTER => self.on_enter = Some(c.to_string()),
+                Some(c) => self.on_leave = Some(c.to_string()),
+                None => return Err(format!("{} must be a command string", name)),
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Names of all aliases
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.aliases.iter().map(|a| a.name.as_str())
+    }
+
+    /// Error for a missing alias, with suggestions from this section
+    pub fn not_found(&self, name: &str) -> Error {
+        Error::NoCmdStringFound(
+            self.key.clone(),
+            name.to_string(),
+            suggestions(name, self.names()),
+        )
+    }
+
+    /// Remove the alias called `name`
+    pub fn remove(&mut self, name: &str) -> Option<Alias> {
+        let idx = self.aliases.iter().position(|a| a.name == name)?;
+        Some(self.aliases.remove(idx))
+    }
+}
+
+/// A parsed config file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Location of the file
+    pub path: PathBuf,
+    /// Sections in declaration order
+    pub sections: Vec<Section>,
+    /// Text of the file, changes are applied to it so formatting and comments are kept
+    pub text: String,
+}
+
+impl Config {
+    /// Read and parse the config file at `path`
+    /// Error:
+    /// - Could not read file
+    /// - Could not parse TOML
+    pub fn load(path: PathBuf) -> Result<Self, Error> {
+        let config_str = match fs::read_to_string(&path) {
+            Err(e) => return Err(Error::NoFile(path, e)),
+            Ok(s) => s,
+        };
+        parse_config(&path, &config_str)
+    }
+
+    /// Serialize to TOML
+    /// Error:
+    /// - Could not serialize TOML
+    pub fn to_toml(&self) -> Result<String, Error> {
+        match toml::to_string_pretty(self) {
+            Err(e) => Err(Error::UnableToSerialize(e)),
+            Ok(s) => Ok(s),
+        }
+    }
+
+    /// Write the changed text back to the file, replacing it atomically
+    ///
+    /// The previous version is kept as a backup, see [`backup`]. A new file, or a file that
+    /// was allowed to run aliases, is allowed with its new contents, see [`trust`].
+    /// Error:
+    /// - Could not create the directory of the file
+    /// - Could not write file
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            if let Err(e) = fs::create_dir_all(dir) {
+                return Err(Error::ConfigDirCreation(dir.to_path_buf(), e));
+            }
+        }
+        let allowed = match fs::read_to_string(&self.path) {
+            Err(e) => e.kind() == io::ErrorKind::NotFound,
+            Ok(text) => trust::is_allowed(&self.path, &text),
+        };
+        if let Err(e) = backup(&self.path) {
+            return Err(Error::ConfigFileWrite(self.path.clone(), e));
+        }
+        if let Err(e) = write_atomic(&self.path, &self.text) {
+            return Err(Error::ConfigFileWrite(self.path.clone(), e));
+        }
+        if allowed {
+            trust::allow(&self.path, &self.text)?;
+        }
+        Ok(())
+    }
+
+    /// Apply a change to [`Config::text`]
+    /// Error:
+    /// - The text can not be edited, or the change failed
+    fn edit<F>(&mut self, change: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut DocumentMut) -> Result<(), String>,
+    {
+        let mut doc = match self.text.parse::<DocumentMut>() {
+            Err(e) => return Err(Error::UnableToEdit(self.path.clone(), e.to_string())),
+            Ok(d) => d,
+        };
+        if let Err(e) = change(&mut doc) {
+            return Err(Error::UnableToEdit(self.path.clone(), e));
+        }
+        self.text = doc.to_string();
+        Ok(())
+    }
+
+    /// Directory relative section keys are relative to
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or_else(|| Path::new(""))
+    }
+
+    /// Section with the key `key`
+    pub fn section(&self, key: &Path) -> Option<&Section> {
+        self.sections.iter().find(|s| s.key == key)
+    }
+
+    /// Mutable section with the key `key`
+    pub fn section_mut(&mut self, key: &Path) -> Option<&mut Section> {
+        self.sections.iter_mut().find(|s| s.key == key)
+    }
+
+    /// Combine `other` into this config
+    ///
+    /// Sections with the same key are joined, aliases of `other` replace aliases with the same
+    /// name.
+    pub fn merge(&mut self, other: Config) {
+        for section in other.sections {
+            match self.section_mut(&section.key) {
+                Some(s) => {
+                    for alias in section.aliases {
+                        s.insert(alias);
+                    }
+                }
+                None => self.sections.push(section),
+            }
+        }
+    }
+
+    fn section_or_err(&mut self, key: &Path) -> Result<&mut Section, Error> {
+        let dir = self.dir().join(key);
+        match self.section_mut(key) {
+            Some(s) => Ok(s),
+            None => Err(Error::NoConfigForPath(dir)),
+        }
+    }
+
+    /// Add an empty section for `key`, unless there already is one
+    /// Error:
+    /// - The text can not be edited
+    pub fn add_section(&mut self, key: &Path) -> Result<(), Error> {
+        if self.section(key).is_some() {
+            return Ok(());
+        }
+        let key_str = key.to_string_lossy().to_string();
+        self.edit(|doc| {
+            edit::add_section(doc, &key_str);
+            Ok(())
+        })?;
+        self.sections.push(Section::new(key));
+        Ok(())
+    }
+
+    /// Remove the section `key` with all its aliases
+    /// Error:
+    /// - Section does not exist
+    pub fn remove_section(&mut self, key: &Path) -> Result<Section, Error> {
+        let idx = match self.sections.iter().position(|s| s.key == key) {
+            Some(i) => i,
+            None => return Err(Error::NoConfigForPath(self.dir().join(key))),
+        };
+        let key_str = key.to_string_lossy().to_string();
+        self.edit(|doc| {
+            edit::remove_section(doc, &key_str);
+            Ok(())
+        })?;
+        Ok(self.sections.remove(idx))
+    }
+
+    /// Give the section `old` the key `new`
+    /// Error:
+    /// - Section `old` does not exist
+    /// - There already is a section `new`
+    pub fn rename_section(&mut self, old: &Path, new: &Path) -> Result<(), Error> {
+        if self.section(new).is_some() {
+            return Err(Error::SectionExists(self.path.clone(), new.to_path_buf()));
+        }
+        self.section_or_err(old)?;
+
+        let (old_str, new_str) = (old.to_string_lossy(), new.to_string_lossy());
+        self.edit(|doc| {
+            edit::rename_section(doc, &old_str, &new_str);
+            Ok(())
+        })?;
+        self.section_mut(old).unwrap().key = new.to_path_buf();
+        Ok(())
+    }
+
+    /// Add a new alias to the section `key`
+    /// Error:
+    /// - Section does not exist
+    /// - Alias already exists
+    pub fn add(&mut self, key: &Path, alias: Alias) -> Result<(), Error> {
+        if SETTINGS.contains(&alias.name.as_str()) {
+            return Err(Error::ReservedName(alias.name));
+        }
+        let section = self.section_or_err(key)?;
+        if section.get(&alias.name).is_some() {
+            return Err(Error::CmdStringExists(key.to_path_buf(), alias.name));
+        }
+
+        let key_str = key.to_string_lossy().to_string();
+        self.edit(|doc| edit::set_alias(doc, &key_str, &alias))?;
+        self.section_or_err(key)?.insert(alias);
+        Ok(())
+    }
+
+    /// Change the command of an existing alias in the section `key`
+    /// Error:
+    /// - Section does not exist
+    /// - Alias does not exist
+    pub fn update(&mut self, key: &Path, name: &str, cmd: String) -> Result<(), Error> {
+        let section = self.section_or_err(key)?;
+        let mut alias = match section.get(name) {
+            Some(a) => a.clone(),
+            None => return Err(section.not_found(name)),
+        };
+        alias.cmd = cmd;
+        alias.script = None;
+        alias.interpreter = None;
+
+        let key_str = key.to_string_lossy().to_string();
+        self.edit(|doc| edit::set_alias(doc, &key_str, &alias))?;
+        self.section_or_err(key)?.insert(alias);
+        Ok(())
+    }
+
+    /// Put `alias` into the section `key` as it is, replacing the alias with the same name
+    /// Error:
+    /// - Section does not exist
+    pub fn restore(&mut self, key: &Path, alias: Alias) -> Result<(), Error> {
+        self.section_or_err(key)?;
+
+        let key_str = key.to_string_lossy().to_string();
+        self.edit(|doc| edit::set_alias(doc, &key_str, &alias))?;
+        self.section_or_err(key)?.insert(alias);
+        Ok(())
+    }
+
+    /// Remove an alias from the section `key`
+    /// Error:
+    /// - Section does not exist
+    /// - Alias does not exist
+    pub fn delete(&mut self, key: &Path, name: &str) -> Result<Alias, Error> {
+        let section = self.section_or_err(key)?;
+        if section.get(name).is_none() {
+            return Err(section.not_found(name));
+        }
+
+        let key_str = key.to_string_lossy().to_string();
+        self.edit(|doc| edit::remove_alias(doc, &key_str, name))?;
+        Ok(self.section_or_err(key)?.remove(name).unwrap())
+    }
+
+    /// Give the alias `old` of the section `key` the name `new`
+    /// Error:
+    /// - Section does not exist
+    /// - `old` does not exist
+    /// - `new` is a setting or already exists
+    pub fn rename(&mut self, key: &Path, old: &str, new: &str) -> Result<(), Error> {
+        if SETTINGS.contains(&new) {
+            return Err(Error::ReservedName(new.to_string()));
+        }
+        let section = self.section_or_err(key)?;
+        if section.get(old).is_none() {
+            return Err(section.not_found(old));
+        }
+        if section.get(new).is_some() {
+            return Err(Error::CmdStringExists(key.to_path_buf(), new.to_string()));
+        }
+
+        let key_str = key.to_string_lossy().to_string();
+        self.edit(|doc| edit::rename_alias(doc, &key_str, old, new))?;
+        self.section_or_err(key)?.get_mut(old).unwrap().name = new.to_string();
+        Ok(())
+    }
+}
+
+/// Section key for [`Section::prefix_match`]
+const PREFIX_MATCH: &str = "prefix_match";
+/// Section key for [`Section::fallback`]
+const FALLBACK: &str = "fallback";
+
+/// Section key for [`Section::discover`]
+const DISCOVER: &str = "discover";
+
+/// Section key for [`Section::scripts_dir`]
+const SCRIPTS_DIR: &str = "scripts_dir";
+
+/// Section key for [`Section::on_enter`]
+const ON_ENTER: &str = "on_enter";
+
+/// Section key for [`Section::on_leave`]
+const ON_LEAVE: &str = "on_leave";
+
+/// Section keys that are settings and can not be used as alias names
+pub const SETTINGS: &[&str] = &[
+    PREFIX_MATCH,
+    FALLBACK,
+    DISCOVER,
+    SCRIPTS_DIR,
+    ON_ENTER,
+    ON_LEAVE,
+];
+
+/// Alias written as a table
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AliasTable {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cmd: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    script: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    interpreter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confirm: Option<Confirm>,
+    // TOML needs plain values before tables
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+}
+
+impl AliasTable {
+    fn into_alias(self, name: &str) -> Result<Alias, String> {
+        let cmd = match (self.cmd, &self.script) {
+            (Some(cmd), None) => cmd,
+            (None, Some(_)) => String::new(),
+            _ => return Err("needs either cmd or script".to_string()),
+        };
+        if self.interpreter.is_some() && self.script.is_none() {
+            return Err("interpreter needs a script".to_string());
+        }
+
+        Ok(Alias {
+            script: self.script,
+            interpreter: self.interpreter,
+            description: self.description,
+            env: self.env,
+            confirm: self.confirm,
+            ..Alias::new(name, &cmd)
+        })
+    }
+}
+
+impl From<&Alias> for AliasTable {
+    fn from(alias: &Alias) -> Self {
+        AliasTable {
+            cmd: match alias.script {
+                Some(_) => None,
+                None => Some(alias.cmd.clone()),
+            },
+            script: alias.script.clone(),
+            interpreter: alias.interpreter.clone(),
+            description: alias.description.clone(),
+            confirm: alias.confirm.clone(),
+            env: alias.env.clone(),
+        }
+    }
+}
+
+impl Serialize for Alias {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_plain() {
+            return self.cmd.serialize(serializer);
+        }
+        AliasTable::from(self).serialize(serializer)
+    }
+}
+
+impl Serialize for Section {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(prefix_match) = self.prefix_match {
+            map.serialize_entry(PREFIX_MATCH, &prefix_match)?;
+        }
+        if let Some(fallback) = &self.fallback {
+            map.serialize_entry(FALLBACK, fallback)?;
+        }
+        if let Some(discover) = &self.discover {
+            let tools = discover.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+            map.serialize_entry(DISCOVER, &tools)?;
+        }
+        if let Some(scripts_dir) = &self.scripts_dir {
+            map.serialize_entry(SCRIPTS_DIR, scripts_dir)?;
+        }
+        if let Some(on_enter) = &self.on_enter {
+            map.serialize_entry(ON_ENTER, on_enter)?;
+        }
+        if let Some(on_leave) = &self.on_leave {
+            map.serialize_entry(ON_LEAVE, on_leave)?;
+        }
+        // TOML needs plain values before tables
+        for alias in self.aliases.iter().filter(|a| a.is_plain()) {
+            map.serialize_entry(&alias.name, alias)?;
+        }
+        for alias in self.aliases.iter().filter(|a| !a.is_plain()) {
+            map.serialize_entry(&alias.name, alias)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Config {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.sections.len()))?;
+        for section in &self.sections {
+            map.serialize_entry(&section.key, section)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Config {
+    /// Sections and aliases come out in the order of the file, without a [`Source`]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = toml::value::Map::<String, toml::Value>::deserialize(deserializer)?;
+
+        let mut sections = vec![];
+        for (key, raw_section) in raw {
+            let key = PathBuf::from(key);
+            let raw_section = match raw_section {
+                toml::Value::Table(t) => t,
+                _ => return Err(de::Error::custom(format!("{:?} must be a section", key))),
+            };
+            let mut section = Section::new(&key);
+            for (name, value) in raw_section {
+                match section.set(&name, &value) {
+                    Err(e) => return Err(de::Error::custom(format!("{} in section {:?}", e, key))),
+                    Ok(true) => continue,
+                    Ok(false) => {}
+                }
+
+                let alias = match value {
+                    toml::Value::String(cmd) => Alias::new(&name, &cmd),
+                    toml::Value::Table(_) => {
+                        let alias = value
+                            .try_into::<AliasTable>()
+                            .map_err(|e| e.to_string())
+                            .and_then(|t| t.into_alias(&name));
+                        match alias {
+                            Err(e) => {
+                                return Err(de::Error::custom(format!(
+                                    "invalid alias {:?} in section {:?}: {}",
+                                    name, key, e
+                                )))
+                            }
+                            Ok(a) => a,
+                        }
+                    }
+                    _ => {
+                        return Err(de::Error::custom(format!(
+                            "alias {:?} in section {:?} must be a command string or a table",
+                            name, key
+                        )))
+                    }
+                };
+                section.aliases.push(alias);
+            }
+            sections.push(section);
+        }
+
+        Ok(Config {
+            path: PathBuf::new(),
+            sections,
+            text: String::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        let mut sub = Section::new(Path::new("sub"));
+        sub.prefix_match = Some(true);
+        sub.fallback = Some("make {}".to_string());
+        sub.discover = Some(vec![Tool::Make, Tool::Npm]);
+        sub.scripts_dir = Some(PathBuf::from("bin"));
+        sub.on_enter = Some("source .env".to_string());
+        sub.on_leave = Some("unset TOKEN".to_string());
+        sub.aliases.push(Alias::new("b", "make"));
+
+        let mut gen = Alias::new("gen", "");
+        gen.script = Some("print('hi')\n".to_string());
+        gen.interpreter = Some("python3".to_string());
+        let mut deploy = Alias::new("deploy", "./deploy.sh");
+        deploy.description = Some("Ship it".to_string());
+        deploy.env.insert("STAGE".to_string(), "prod".to_string());
+        deploy.confirm = Some(Confirm::Message("Deploy to prod?".to_string()));
+        let mut clean = Alias::new("clean", "rm -rf target");
+        clean.confirm = Some(Confirm::Ask(true));
+
+        let mut root = Section::new(Path::new("."));
+        root.aliases = vec![Alias::new("t", "cargo test"), gen, deploy, clean];
+
+        Config {
+            path: PathBuf::new(),
+            sections: vec![sub, root],
+            text: String::new(),
+        }
+    }
+
+    #[test]
+    fn config_survives_a_round_trip_through_toml() {
+        let config = config();
+        let text = toml::to_string(&config).unwrap();
+        assert!(text.find("[sub]").unwrap() < text.find("[\".\"]").unwrap());
+
+        let parsed = toml::from_str::<Config>(&text).unwrap();
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn merge_joins_sections_and_replaces_aliases() {
+        let mut config = config();
+        let mut other = Config::default();
+        let mut root = Section::new(Path::new("."));
+        root.aliases = vec![
+            Alias::new("t", "cargo nextest run"),
+            Alias::new("r", "cargo run"),
+        ];
+        other.sections = vec![root, Section::new(Path::new("docs"))];
+        config.merge(other);
+
+        let keys = config
+            .sections
+            .iter()
+            .map(|s| s.key.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [Path::new("sub"), Path::new("."), Path::new("docs")]);
+        let root = config.section(Path::new(".")).unwrap();
+        assert_eq!(
+            root.names().collect::<Vec<_>>(),
+            ["t", "gen", "deploy", "clean", "r"]
+        );
+        assert_eq!(root.get("t").unwrap().cmd, "cargo nextest run");
+    }
+}
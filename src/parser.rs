@@ -16,20 +16,148 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::error::Error;
-use std::{collections::HashMap, path::PathBuf};
+use crate::{
+    error::Error,
+    model::{Config, Source},
+};
+use std::{collections::HashMap, path::Path};
+use toml_edit::ImDocument;
 
-/// Map of directory name to a key value pair of `entry`
-pub type Entries = HashMap<PathBuf, Entry>;
-/// Map of alias to actual command
-pub type Entry = HashMap<String, String>;
-
-/// Parses toml file into a list of entries
+/// Parses the toml text of the config file at `path`
+///
+/// Every section and alias gets the line it is defined at.
 /// Error:
 /// - Could not parse TOML
-pub fn parse_toml(c: &str) -> Result<Entries, Error> {
-    match toml::from_str::<Entries>(c) {
-        Ok(e) => Ok(e),
-        Err(e) => Err(Error::UnableToParse(e)),
+pub fn parse_config(path: &Path, c: &str) -> Result<Config, Error> {
+    let mut config = match toml::from_str::<Config>(c) {
+        Ok(e) => e,
+        Err(e) => return Err(Error::UnableToParse(path.to_path_buf(), e)),
+    };
+    config.path = path.to_path_buf();
+    config.text = c.to_string();
+
+    let lines = key_lines(c);
+    let line_of = |keys: &[&str]| {
+        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        lines.get(&keys).copied().unwrap_or(0)
+    };
+
+    for section in &mut config.sections {
+        let key = section.key.to_string_lossy().to_string();
+        section.source = Source::new(path, line_of(&[&key]));
+        for alias in &mut section.aliases {
+            alias.source = Source::new(path, line_of(&[&key, &alias.name]));
+        }
+    }
+
+    Ok(config)
+}
+
+/// Line (1-based) of the byte at `offset` in `c`
+fn line_at(c: &str, offset: usize) -> usize {
+    c[..offset.min(c.len())].matches('\n').count() + 1
+}
+
+/// Line (1-based) of every section and alias key, from the spans of toml_edit
+///
+/// `["path"]` on line 1 and `r = "cmd"` on line 2 give `["path"] => 1` and
+/// `["path", "r"] => 2`. A section only written as `["path".r]` headers gets the line of its
+/// first alias.
+fn key_lines(c: &str) -> HashMap<Vec<String>, usize> {
+    let mut lines = HashMap::new();
+    let doc = match ImDocument::parse(c) {
+        Ok(d) => d,
+        Err(_) => return lines,
+    };
+
+    for (key, item) in doc.as_table().iter() {
+        let mut first = doc
+            .as_table()
+            .key(key)
+            .and_then(|k| k.span())
+            .or_else(|| item.as_table().and_then(|t| t.span()))
+            .map(|s| line_at(c, s.start));
+
+        if let Some(table) = item.as_table_like() {
+            for (name, alias) in table.iter() {
+                let span = table
+                    .key(name)
+                    .and_then(|k| k.span())
+                    .or_else(|| alias.span());
+                if let Some(line) = span.map(|s| line_at(c, s.start)) {
+                    lines.insert(vec![key.to_string(), name.to_string()], line);
+                    first = Some(first.map_or(line, |f| f.min(line)));
+                }
+            }
+        }
+        if let Some(line) = first {
+            lines.insert(vec![key.to_string()], line);
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(lines: &HashMap<Vec<String>, usize>, keys: &[&str]) -> Option<usize> {
+        let keys = keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        lines.get(&keys).copied()
+    }
+
+    #[test]
+    fn key_lines_of_sections_and_aliases() {
+        let text = "# comment\n\
+                    [\"/a\"]\n\
+                    b = \"make\"\n\
+                    \n\
+                    t = { cmd = \"make test\" }\n\
+                    [\"/b\"]\n\
+                    b = \"cargo build\"\n";
+        let lines = key_lines(text);
+
+        assert_eq!(line(&lines, &["/a"]), Some(2));
+        assert_eq!(line(&lines, &["/a", "b"]), Some(3));
+        assert_eq!(line(&lines, &["/a", "t"]), Some(5));
+        assert_eq!(line(&lines, &["/b"]), Some(6));
+        assert_eq!(line(&lines, &["/b", "b"]), Some(7));
+    }
+
+    #[test]
+    fn key_lines_of_dotted_headers() {
+        let text = "[\"/a\".py]\n\
+                    script = \"print(1)\"\n\
+                    \n\
+                    [\"/a\"]\n\
+                    b = \"make\"\n";
+        let lines = key_lines(text);
+
+        assert_eq!(line(&lines, &["/a", "py"]), Some(1));
+        assert_eq!(line(&lines, &["/a", "b"]), Some(5));
+        assert_eq!(line(&lines, &["/a"]), Some(1));
+    }
+
+    #[test]
+    fn key_lines_of_broken_text() {
+        assert!(key_lines("[\"/a\"\nb = ").is_empty());
+    }
+
+    #[test]
+    fn parse_config_keeps_file_order() {
+        let text = "[\"/z\"]\nb = \"make\"\na = \"make a\"\n[\"/a\"]\nc = \"make c\"\n";
+        let config = parse_config(Path::new("/d.toml"), text).unwrap();
+
+        let keys = config
+            .sections
+            .iter()
+            .map(|s| s.key.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [Path::new("/z"), Path::new("/a")]);
+        let names = config.sections[0].names().collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a"]);
+        assert_eq!(config.sections[0].aliases[1].source.line, 3);
+        assert_eq!(config.sections[1].source.line, 4);
     }
 }
@@ -0,0 +1,142 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::{error::Error, resolver::Resolution};
+use dialoguer::{FuzzySelect, Input};
+
+/// Alias picked from the list, with the extra arguments
+pub struct Pick {
+    /// Name of the alias
+    pub name: String,
+    /// Arguments given in the prompt
+    pub args: Vec<String>,
+}
+
+/// One line per alias, name, command and description in columns
+fn items(resolution: &Resolution) -> (Vec<String>, Vec<String>) {
+    let aliases = resolution.aliases();
+    let name_width = aliases.keys().map(|n| n.chars().count()).max().unwrap_or(0);
+    let cmd_width = aliases
+        .values()
+        .map(|a| a.summary().chars().count().min(40))
+        .max()
+        .unwrap_or(0);
+
+    let mut names = vec![];
+    let mut items = vec![];
+    for (name, alias) in aliases {
+        let summary = alias.summary();
+        let item = match &alias.description {
+            Some(d) => format!(
+                "{:nw$}  {:cw$}  {}",
+                name,
+                summary,
+                d,
+                nw = name_width,
+                cw = cmd_width
+            ),
+            None => format!("{:nw$}  {}", name, summary, nw = name_width),
+        };
+        names.push(name.to_string());
+        items.push(item.lines().next().unwrap_or_default().to_string());
+    }
+    (names, items)
+}
+
+/// Let the user filter the aliases of `resolution` and pick one
+///
+/// `None` if the user cancelled with escape.
+/// Error:
+/// - The terminal could not be read or written
+pub fn pick(resolution: &Resolution) -> Result<Option<Pick>, Error> {
+    let (names, items) = items(resolution);
+
+    let selected = FuzzySelect::new()
+        .with_prompt("Alias")
+        .items(&items)
+        .default(0)
+        .interact_opt();
+    let name = match selected {
+        Err(dialoguer::Error::IO(e)) => return Err(Error::Prompt(e)),
+        Ok(None) => return Ok(None),
+        Ok(Some(i)) => names[i].clone(),
+    };
+
+    let args = Input::<String>::new()
+        .with_prompt(format!("Arguments for {} (enter for none)", name))
+        .allow_empty(true)
+        .interact_text();
+    let args = match args {
+        Err(dialoguer::Error::IO(e)) => return Err(Error::Prompt(e)),
+        Ok(a) => split_args(&a),
+    };
+
+    Ok(Some(Pick { name, args }))
+}
+
+/// Split a line into arguments like `sh` would, honouring quotes and backslashes
+pub fn split_args(line: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    arg.get_or_insert_with(String::new).push(next);
+                }
+            }
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_args_on_whitespace() {
+        assert_eq!(split_args("  a b\tc  "), ["a", "b", "c"]);
+        assert!(split_args("   ").is_empty());
+    }
+
+    #[test]
+    fn split_args_with_quotes_and_backslashes() {
+        assert_eq!(
+            split_args(r#"'a b' "c \"d\"" e\ f 'g\h' """#),
+            ["a b", "c \"d\"", "e f", "g\\h", ""]
+        );
+    }
+
+    #[test]
+    fn split_args_joins_quoted_parts_of_a_word() {
+        assert_eq!(split_args(r#"--name="a b"x"#), ["--name=a bx"]);
+    }
+}
@@ -0,0 +1,604 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! - Absolute keys are used as is.
+//! - Relative keys (including ones with `..`) are relative to the directory of the config file.
+//! - Both the section directory and the current directory are canonicalized, so symlinks are
+//!   resolved before comparing.
+//! - A section whose directory can not be resolved (deleted, moved, no permission) is stale.
+//!   It is skipped and reported as a [`Warning`].
+//! - A section applies to every directory inside of it. Aliases of deeper sections shadow the
+//!   aliases of the sections around them. For sections of the same directory, the nearest
+//!   config file wins over the global one.
+
+use crate::{
+    config::ConfigSet,
+    discover::{discover, scripts},
+    error::Error,
+    model::{Alias, Config, Section, Source},
+    suggest::{match_name, suggestions},
+    utils::{CommandParams, Launch},
+};
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// A section of a config file that applies to the current directory
+pub struct Candidate<'a> {
+    /// Config file the section is defined in
+    pub config: &'a Config,
+    /// The section itself
+    pub section: &'a Section,
+    /// Canonical directory the key points to
+    pub path: PathBuf,
+    /// Aliases from the scripts directory and the tools of the directory, see [`crate::discover`]
+    pub discovered: Vec<Alias>,
+}
+
+impl<'a> Candidate<'a> {
+    /// Alias called `name`, aliases of the section win over discovered ones
+    pub fn get(&self, name: &str) -> Option<&Alias> {
+        match self.section.get(name) {
+            Some(a) => Some(a),
+            None => self.discovered.iter().find(|a| a.name == name),
+        }
+    }
+
+    /// Aliases of the section followed by the discovered aliases they do not shadow
+    pub fn aliases(&self) -> impl Iterator<Item = &Alias> {
+        let discovered = self
+            .discovered
+            .iter()
+            .filter(move |a| self.section.get(&a.name).is_none());
+        self.section.aliases.iter().chain(discovered)
+    }
+}
+
+/// Non fatal problems found while resolving
+pub enum Warning {
+    /// Section key points to a directory that can not be accessed
+    StaleSection(PathBuf, PathBuf, io::Error),
+    /// A tool file could not be read or parsed for discovery
+    Discovery(Source, String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::StaleSection(config, key, e) => {
+                write!(
+                    f,
+                    "ignoring section {:?} in {}: {}",
+                    key,
+                    config.display(),
+                    e
+                )
+            }
+            Warning::Discovery(source, e) => {
+                write!(f, "could not discover aliases in {}: {}", source, e)
+            }
+        }
+    }
+}
+
+/// Sections that apply to a directory, most specific first
+pub struct Resolution<'a> {
+    /// Directory the sections were resolved for
+    pub pwd: PathBuf,
+    /// Matching sections, the first one shadows the rest
+    pub candidates: Vec<Candidate<'a>>,
+    /// Stale sections and broken tool files
+    pub warnings: Vec<Warning>,
+}
+
+impl<'a> Resolution<'a> {
+    /// Most specific section for the directory, this is where new aliases go
+    /// Error:
+    /// - No section applies to the directory
+    pub fn best(&self) -> Result<&Candidate<'a>, Error> {
+        match self.candidates.first() {
+            Some(c) => Ok(c),
+            None => Err(Error::NoConfigForPath(self.pwd.clone())),
+        }
+    }
+
+    /// Report stale sections on stderr without failing
+    pub fn print_warnings(&self) {
+        for warning in &self.warnings {
+            eprintln!("warning: {}", warning);
+        }
+    }
+
+    /// Whether a unique prefix selects an alias, set by the most specific section that says so
+    pub fn prefix_match(&self) -> bool {
+        self.candidates
+            .iter()
+            .find_map(|c| c.section.prefix_match)
+            .unwrap_or(false)
+    }
+
+    /// An alias and the section it is taken from
+    ///
+    /// A unique prefix of the name is enough if [`Resolution::prefix_match`] is on.
+    /// Error:
+    /// - No section applies to the directory
+    /// - No section defines the alias, with suggestions for similar names
+    pub fn get(&self, name: &str) -> Result<(&Candidate<'a>, &Alias), Error> {
+        self.best()?;
+        let aliases = self.aliases();
+        let prefix = self.prefix_match();
+
+        let name = match match_name(name, aliases.keys().copied(), prefix) {
+            Some(n) => n,
+            None => {
+                return Err(Error::NoCmdStringFound(
+                    self.pwd.clone(),
+                    name.to_string(),
+                    suggestions(name, aliases.keys().copied()),
+                ))
+            }
+        };
+
+        for candidate in &self.candidates {
+            if let Some(alias) = candidate.get(name) {
+                return Ok((candidate, alias));
+            }
+        }
+        unreachable!("alias {} is in the merged aliases", name)
+    }
+
+    /// Full name of the alias `name` in the most specific section, used to change or remove it
+    /// Error:
+    /// - No section applies to the directory
+    /// - The alias is discovered or defined by a less specific section
+    /// - The section does not define the alias, with suggestions from its names
+    pub fn local_name(&self, name: &str) -> Result<String, Error> {
+        let section = self.best()?.section;
+        if let Some(n) = match_name(name, section.names(), self.prefix_match()) {
+            return Ok(n.to_string());
+        }
+        for candidate in &self.candidates {
+            match candidate.get(name) {
+                Some(alias) if alias.discovered => {
+                    return Err(Error::Discovered(alias.name.clone(), alias.source.clone()))
+                }
+                Some(_) => {
+                    return Err(Error::OtherSection(
+                        candidate.config.path.clone(),
+                        candidate.section.key.clone(),
+                        name.to_string(),
+                    ))
+                }
+                None => {}
+            }
+        }
+        Err(section.not_found(name))
+    }
+
+    /// All aliases available in the directory, sections merged
+    pub fn aliases(&self) -> BTreeMap<&str, &Alias> {
+        let mut aliases = BTreeMap::new();
+        for candidate in self.candidates.iter().rev() {
+            for alias in candidate.aliases() {
+                aliases.insert(alias.name.as_str(), alias);
+            }
+        }
+        aliases
+    }
+
+    /// Lines telling where the alias `name` comes from and what would be executed for it
+    /// Error:
+    /// - There is neither an alias nor a fallback for `name`
+    pub fn explain(&self, name: &str, args: Vec<String>) -> Result<Vec<String>, Error> {
+        let expanded = self.command(name, args)?;
+
+        let mut lines = vec![];
+        match self.get(name) {
+            Ok((found, alias)) => {
+                lines.push(format!("config:   {}", alias.source));
+                lines.push(format!(
+                    "section:  {:?} ({})",
+                    found.section.key,
+                    found.path.display()
+                ));
+                let shadowed = self
+                    .candidates
+                    .iter()
+                    .skip_while(|c| !std::ptr::eq(*c, found))
+                    .skip(1);
+                for candidate in shadowed {
+                    if let Some(a) = candidate.section.get(&alias.name) {
+                        lines.push(format!(
+                            "shadowed: {:?} at {} ({})",
+                            candidate.section.key, a.source, a.cmd
+                        ));
+                    }
+                }
+                if let Some(description) = &alias.description {
+                    lines.push(format!("about:    {}", description));
+                }
+                if let Some(question) = alias.confirmation() {
+                    lines.push(format!("confirm:  {}", question));
+                }
+                if alias.script.is_none() {
+                    lines.push(format!("command:  {}", alias.cmd));
+                }
+            }
+            Err(_) => {
+                let found = self.fallback().unwrap();
+                lines.push(format!("config:   {}", found.section.source));
+                lines.push(format!(
+                    "section:  {:?} ({})",
+                    found.section.key,
+                    found.path.display()
+                ));
+                lines.push(format!(
+                    "fallback: {}",
+                    found.section.fallback.as_deref().unwrap_or_default()
+                ));
+            }
+        }
+        lines.push(format!("expanded: {}", expanded));
+        match &expanded.launch {
+            Launch::Shell => lines.push("shell:    sh -c".to_string()),
+            Launch::File(_) => lines.push("shell:    none, executed directly".to_string()),
+            Launch::Script { interpreter, body } => {
+                match interpreter {
+                    Some(i) => lines.push(format!("shell:    {} <temporary file>", i)),
+                    None => lines.push("shell:    none, shebang of the script".to_string()),
+                }
+                for line in body.lines() {
+                    lines.push(format!("  | {}", line));
+                }
+            }
+        }
+        lines.push(format!("cwd:      {}", self.pwd.display()));
+        if expanded.env.is_empty() {
+            lines.push("env:      inherited".to_string());
+        } else {
+            for (k, v) in &expanded.env {
+                lines.push(format!("env:      {}={}", k, v));
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Turn a list of aliases into commands to execute
+    ///
+    /// Arguments after `--` are given to the last alias only
+    ///
+    /// dipse build run -- args
+    /// Error:
+    /// - An alias could not be found
+    pub fn plan(&self, cmd_list: &[String]) -> Result<Vec<CommandParams>, Error> {
+        let (names, args) = match cmd_list.iter().position(|s| s == "--") {
+            Some(idx) => (&cmd_list[..idx], &cmd_list[idx + 1..]),
+            None => (cmd_list, &[][..]),
+        };
+
+        let mut cmd_params = vec![];
+        for (i, name) in names.iter().enumerate() {
+            let params = if i + 1 == names.len() {
+                args.to_vec()
+            } else {
+                vec![]
+            };
+            cmd_params.push(self.command(name, params)?);
+        }
+        Ok(cmd_params)
+    }
+
+    /// Most specific section with a `fallback`
+    pub fn fallback(&self) -> Option<&Candidate<'a>> {
+        self.candidates
+            .iter()
+            .find(|c| c.section.fallback.is_some())
+    }
+
+    /// Command to run for the alias `name` with `params`
+    ///
+    /// Aliases that are not defined use the fallback of the sections, if there is one.
+    /// Error:
+    /// - The alias is not defined and there is no fallback
+    pub fn command(&self, name: &str, params: Vec<String>) -> Result<CommandParams, Error> {
+        let alias = match self.get(name) {
+            Ok((_, a)) => a,
+            Err(e @ Error::NoCmdStringFound(..)) => {
+                return match self.fallback() {
+                    Some(c) => Ok(c.section.fallback_cmd(name, &params).unwrap()),
+                    None => Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(CommandParams {
+            cmd_str: alias.summary(),
+            params,
+            env: alias.env.clone(),
+            launch: alias.launch(),
+            confirm: alias.confirmation(),
+        })
+    }
+}
+
+/// Directory that a section key refers to
+pub fn section_dir(config_dir: &Path, key: &Path) -> io::Result<PathBuf> {
+    config_dir.join(key).canonicalize()
+}
+
+/// Finds the sections of a [`ConfigSet`] that apply to a directory
+pub struct Resolver<'a> {
+    set: &'a ConfigSet,
+}
+
+impl<'a> Resolver<'a> {
+    /// Resolver over all files of `set`
+    pub fn new(set: &'a ConfigSet) -> Self {
+        Resolver { set }
+    }
+
+    /// Find the sections that apply to `pwd`
+    /// Error:
+    /// - A config path or the current directory can not be canonicalized
+    pub fn resolve(&self, pwd: &Path) -> Result<Resolution<'a>, Error> {
+        let pwd = match pwd.canonicalize() {
+            Err(_) => return Err(Error::CurrentDir),
+            Ok(p) => p,
+        };
+
+        let mut candidates = vec![];
+        let mut warnings = vec![];
+        for (priority, config) in self.set.files.iter().enumerate() {
+            let config_path = match config.path.canonicalize() {
+                Err(e) => return Err(Error::ConfigPath(e)),
+                Ok(c) => c,
+            };
+            let config_dir = config_path.parent().unwrap_or_else(|| Path::new("/"));
+
+            for section in &config.sections {
+                let path = match section_dir(config_dir, &section.key) {
+                    Err(e) => {
+                        warnings.push(Warning::StaleSection(
+                            config.path.clone(),
+                            section.key.clone(),
+                            e,
+                        ));
+                        continue;
+                    }
+                    Ok(p) => p,
+                };
+
+                // Check if the section path is IN the pwd
+                // This allows dipse to work when inside a nested system
+                if !pwd.starts_with(&path) {
+                    continue;
+                }
+
+                let mut discovered = vec![];
+                if let Some(dir) = &section.scripts_dir {
+                    let (aliases, errors) = scripts(&path.join(dir));
+                    warnings.extend(errors.into_iter().map(|(s, e)| Warning::Discovery(s, e)));
+                    discovered.extend(aliases);
+                }
+                if let Some(tools) = &section.discover {
+                    let (aliases, errors) = discover(&path, tools);
+                    warnings.extend(errors.into_iter().map(|(s, e)| Warning::Discovery(s, e)));
+                    for alias in aliases {
+                        if !discovered.iter().any(|a: &Alias| a.name == alias.name) {
+                            discovered.push(alias);
+                        }
+                    }
+                }
+                candidates.push((
+                    priority,
+                    Candidate {
+                        config,
+                        section,
+                        path,
+                        discovered,
+                    },
+                ));
+            }
+        }
+
+        candidates.sort_by(|(pa, a), (pb, b)| {
+            let depth = b
+                .path
+                .components()
+                .count()
+                .cmp(&a.path.components().count());
+            depth
+                .then(pa.cmp(pb))
+                .then_with(|| a.section.key.cmp(&b.section.key))
+        });
+
+        Ok(Resolution {
+            pwd,
+            candidates: candidates.into_iter().map(|(_, c)| c).collect(),
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_config, utils::Launch};
+
+    /// Config file of `/project` with the sections in `text`
+    fn config(text: &str) -> Config {
+        parse_config(Path::new("/project/.d.toml"), text).unwrap()
+    }
+
+    /// Resolution of `/project/sub` with every section of `config`, in file order
+    fn resolution(config: &Config) -> Resolution<'_> {
+        Resolution {
+            pwd: PathBuf::from("/project/sub"),
+            candidates: config
+                .sections
+                .iter()
+                .map(|section| Candidate {
+                    config,
+                    section,
+                    path: Path::new("/project").join(&section.key),
+                    discovered: vec![],
+                })
+                .collect(),
+            warnings: vec![],
+        }
+    }
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn plan_gives_arguments_to_the_last_alias() {
+        let config = config("[\".\"]\nb = \"cargo build\"\nr = \"cargo run\"\n");
+        let plan = resolution(&config)
+            .plan(&args(&["b", "r", "--", "x", "y"]))
+            .unwrap();
+
+        let cmds = plan.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(cmds, ["cargo build", "cargo run x y"]);
+    }
+
+    #[test]
+    fn plan_takes_the_nearest_section_first() {
+        let config =
+            config("[sub]\nb = \"make\"\n[\".\"]\nb = \"cargo build\"\nt = \"cargo test\"\n");
+        let plan = resolution(&config).plan(&args(&["b", "t"])).unwrap();
+
+        assert_eq!(plan[0].cmd_str, "make");
+        assert_eq!(plan[1].cmd_str, "cargo test");
+    }
+
+    #[test]
+    fn plan_suggests_similar_names() {
+        let config = config("[\".\"]\nbuild = \"make\"\ntest = \"make test\"\n");
+        match resolution(&config).plan(&args(&["buld"])) {
+            Err(Error::NoCmdStringFound(_, name, suggestions)) => {
+                assert_eq!(name, "buld");
+                assert_eq!(suggestions, ["build"]);
+            }
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn plan_matches_unique_prefixes_only_when_enabled() {
+        let off = config("[\".\"]\nbuild = \"make\"\nbench = \"make bench\"\n");
+        assert!(resolution(&off).plan(&args(&["bu"])).is_err());
+
+        let on = config("[\".\"]\nprefix_match = true\nbuild = \"make\"\nbench = \"make bench\"\n");
+        let plan = resolution(&on).plan(&args(&["bu"])).unwrap();
+        assert_eq!(plan[0].cmd_str, "make");
+        assert!(resolution(&on).plan(&args(&["b"])).is_err());
+    }
+
+    #[test]
+    fn plan_uses_the_fallback_for_unknown_aliases() {
+        let config = config("[\".\"]\nfallback = \"npm run {alias} -- {args}\"\nb = \"make\"\n");
+        let plan = resolution(&config)
+            .plan(&args(&["lint", "--", "--fix"]))
+            .unwrap();
+
+        assert_eq!(plan[0].cmd_str, "npm run lint -- --fix");
+        assert!(plan[0].params.is_empty());
+    }
+
+    #[test]
+    fn plan_runs_scripts_with_their_interpreter() {
+        let config = config(
+            "[\".\".py]\n\
+             script = \"print(1)\"\n\
+             interpreter = \"python3\"\n\
+             [\".\".sh]\n\
+             script = \"#!/bin/bash\\necho\"\n",
+        );
+        let plan = resolution(&config)
+            .plan(&args(&["py", "sh", "--", "a"]))
+            .unwrap();
+
+        assert_eq!(
+            plan[0].launch,
+            Launch::Script {
+                interpreter: Some("python3".to_string()),
+                body: "print(1)".to_string(),
+            }
+        );
+        assert_eq!(
+            plan[1].launch,
+            Launch::Script {
+                interpreter: None,
+                body: "#!/bin/bash\necho".to_string(),
+            }
+        );
+        assert_eq!(plan[1].params, ["a"]);
+    }
+
+    #[test]
+    fn plan_carries_env_and_confirmation() {
+        let config = config(
+            "[\".\"]\n\
+             d = { cmd = \"deploy\", env = { STAGE = \"prod\" }, confirm = true }\n\
+             m = { cmd = \"migrate\", confirm = \"Migrate the database?\" }\n\
+             b = { cmd = \"make\", confirm = false }\n",
+        );
+        let plan = resolution(&config).plan(&args(&["d", "m", "b"])).unwrap();
+
+        assert_eq!(plan[0].env.get("STAGE").map(|s| s.as_str()), Some("prod"));
+        assert_eq!(plan[0].confirm.as_deref(), Some("Run d?"));
+        assert_eq!(plan[1].confirm.as_deref(), Some("Migrate the database?"));
+        assert_eq!(plan[2].confirm, None);
+    }
+
+    #[test]
+    fn local_name_suggests_names_of_the_local_section() {
+        let config = config("[sub]\nbuild = \"make\"\n[\".\"]\nbuilt = \"cargo build\"\n");
+        match resolution(&config).local_name("bult") {
+            Err(Error::NoCmdStringFound(key, name, suggestions)) => {
+                assert_eq!(key, Path::new("sub"));
+                assert_eq!(name, "bult");
+                assert!(suggestions.is_empty());
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match resolution(&config).local_name("buil") {
+            Err(Error::NoCmdStringFound(_, _, suggestions)) => assert_eq!(suggestions, ["build"]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn local_name_tells_which_section_defines_the_alias() {
+        let config = config("[sub]\nbuild = \"make\"\n[\".\"]\ntest = \"cargo test\"\n");
+        assert_eq!(resolution(&config).local_name("build").unwrap(), "build");
+        match resolution(&config).local_name("test") {
+            Err(Error::OtherSection(path, key, name)) => {
+                assert_eq!(path, Path::new("/project/.d.toml"));
+                assert_eq!(key, Path::new("."));
+                assert_eq!(name, "test");
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
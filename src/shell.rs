@@ -0,0 +1,464 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Shell integration
+//!
+//! `dipse shell-init <shell>` prints a hook for the shell's rc file. Whenever the directory
+//! changes, the hook runs `dipse __hook`, which prints shell code that:
+//!
+//! 1. runs the `on_leave` command of every section that no longer applies, deepest first,
+//! 2. removes the functions defined for the previous directory, unless they were redefined,
+//! 3. defines a function for every alias of the new directory, calling `dipse <alias>`, unless
+//!    the name already is a command, function or alias of the shell,
+//! 4. runs the `on_enter` command of every section that starts to apply, outermost first.
+//!
+//! `on_enter` and `on_leave` run in the interactive shell itself, so they can change its
+//! environment. Aliases named like shell builtins or keywords do not get a function.
+
+use crate::{
+    config::ConfigSet,
+    error::Error,
+    resolver::{Resolution, Resolver},
+    trust,
+};
+use std::{
+    fmt,
+    fmt::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Shells with a hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// GNU Bash, hooked into `PROMPT_COMMAND`
+    Bash,
+    /// Z shell, hooked into `chpwd_functions`
+    Zsh,
+    /// fish, hooked on changes of `PWD`
+    Fish,
+}
+
+impl Shell {
+    /// Names accepted on the command line
+    pub const NAMES: &'static [&'static str] = &["bash", "zsh", "fish"];
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!(
+                "unknown shell {:?}, expected one of {}",
+                s,
+                Shell::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Builtins and keywords that are not turned into functions
+const RESERVED: &[&str] = &[
+    "alias",
+    "bg",
+    "bind",
+    "break",
+    "builtin",
+    "case",
+    "cd",
+    "command",
+    "continue",
+    "declare",
+    "dipse",
+    "do",
+    "done",
+    "echo",
+    "elif",
+    "else",
+    "end",
+    "esac",
+    "eval",
+    "exec",
+    "exit",
+    "export",
+    "false",
+    "fg",
+    "fi",
+    "for",
+    "function",
+    "functions",
+    "if",
+    "in",
+    "jobs",
+    "kill",
+    "let",
+    "local",
+    "printf",
+    "pwd",
+    "read",
+    "return",
+    "set",
+    "shift",
+    "source",
+    "test",
+    "then",
+    "time",
+    "trap",
+    "true",
+    "type",
+    "typeset",
+    "unset",
+    "until",
+    "wait",
+    "while",
+];
+
+/// Whether the alias `name` can become a shell function
+pub fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !RESERVED.contains(&name)
+}
+
+/// Script to source from the rc file of `shell`
+pub fn init_script(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => r#"_dipse_hook() {
+    local status=$?
+    if [ "$PWD" != "$_DIPSE_PWD" ]; then
+        eval "$(command dipse __hook bash --from "$_DIPSE_PWD" --funcs "$_DIPSE_FUNCS")"
+        _DIPSE_PWD=$PWD
+    fi
+    return $status
+}
+case ";${PROMPT_COMMAND:-};" in
+    *";_dipse_hook;"*) ;;
+    *) PROMPT_COMMAND="_dipse_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}" ;;
+esac
+"#
+        .to_string(),
+        Shell::Zsh => r#"_dipse_hook() {
+    eval "$(command dipse __hook zsh --from "$_DIPSE_PWD" --funcs "$_DIPSE_FUNCS")"
+    _DIPSE_PWD=$PWD
+}
+typeset -ag chpwd_functions
+if (( ! ${chpwd_functions[(I)_dipse_hook]} )); then
+    chpwd_functions=(_dipse_hook $chpwd_functions)
+fi
+_dipse_hook
+"#
+        .to_string(),
+        Shell::Fish => r#"function __dipse_hook --on-variable PWD
+    command dipse __hook fish --from "$_DIPSE_PWD" --funcs "$_DIPSE_FUNCS" | source
+    set -g _DIPSE_PWD $PWD
+end
+__dipse_hook
+"#
+        .to_string(),
+    }
+}
+
+/// Sections of `resolution` with an identity that survives resolving again
+fn sections<'a>(
+    resolution: Option<&'a Resolution>,
+) -> Vec<(&'a Path, &'a Path, Option<&'a str>, Option<&'a str>)> {
+    match resolution {
+        Some(r) => r
+            .candidates
+            .iter()
+            .map(|c| {
+                (
+                    c.config.path.as_path(),
+                    c.section.key.as_path(),
+                    c.section.on_enter.as_deref(),
+                    c.section.on_leave.as_deref(),
+                )
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Shell code moving from the directory of `old` to the directory of `new`
+///
+/// `defined` are the functions the hook defined before, `None` resolutions have no sections.
+pub fn hook(
+    shell: Shell,
+    old: Option<&Resolution>,
+    new: Option<&Resolution>,
+    defined: &[&str],
+) -> String {
+    let mut out = String::new();
+    let old_sections = sections(old);
+    let new_sections = sections(new);
+    let same = |a: &(&Path, &Path, _, _), b: &(&Path, &Path, _, _)| a.0 == b.0 && a.1 == b.1;
+
+    for section in &old_sections {
+        if let (Some(cmd), false) = (section.3, new_sections.iter().any(|s| same(s, section))) {
+            let _ = writeln!(out, "{}", cmd);
+        }
+    }
+
+    let defined = defined
+        .iter()
+        .copied()
+        .filter(|n| is_function_name(n))
+        .collect::<Vec<_>>();
+    // A function the user defined since has the same name, but no longer calls `dipse`
+    for name in &defined {
+        match shell {
+            Shell::Bash | Shell::Zsh => {
+                let _ = writeln!(
+                    out,
+                    "case \"$(typeset -f {0} 2>/dev/null)\" in *\"command dipse {0} --\"*) unset -f {0} ;; esac",
+                    name
+                );
+            }
+            Shell::Fish => {
+                let _ = writeln!(
+                    out,
+                    "functions {0} 2>/dev/null | string match -q '*command dipse {0} --*'; and functions -e {0}",
+                    name
+                );
+            }
+        }
+    }
+
+    let names = match new {
+        Some(r) => r
+            .aliases()
+            .into_keys()
+            .filter(|n| is_function_name(n))
+            .collect::<Vec<_>>(),
+        None => vec![],
+    };
+    // Names the shell resolves already keep their meaning, only the rest become functions
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            let _ = writeln!(out, "_DIPSE_FUNCS=");
+        }
+        Shell::Fish => {
+            let _ = writeln!(out, "set -g _DIPSE_FUNCS");
+        }
+    }
+    for name in &names {
+        match shell {
+            Shell::Bash | Shell::Zsh => {
+                let _ = writeln!(
+                    out,
+                    "if ! type {0} >/dev/null 2>&1; then {0}() {{ command dipse {0} -- \"$@\"; }}; _DIPSE_FUNCS=\"$_DIPSE_FUNCS {0}\"; fi",
+                    name
+                );
+            }
+            Shell::Fish => {
+                let _ = writeln!(
+                    out,
+                    "if not type -q {0}\n    function {0}; command dipse {0} -- $argv; end\n    set -a _DIPSE_FUNCS {0}\nend",
+                    name
+                );
+            }
+        }
+    }
+
+    for section in new_sections.iter().rev() {
+        if let (Some(cmd), false) = (section.2, old_sections.iter().any(|s| same(s, section))) {
+            let _ = writeln!(out, "{}", cmd);
+        }
+    }
+
+    out
+}
+
+/// Shell code for [`hook`] moving from the directory `from` to `pwd`, with the config files
+/// given with `-f` or discovered for each directory
+///
+/// `funcs` are the functions the hook defined before, separated by spaces. Config files that
+/// are not allowed are reported on stderr and their sections ignored.
+/// Error:
+/// - A config file for `pwd` could not be read or parsed
+pub fn hook_for(
+    shell: Shell,
+    config_path: Option<PathBuf>,
+    pwd: &Path,
+    from: Option<PathBuf>,
+    funcs: &str,
+) -> Result<String, Error> {
+    let new_set = match ConfigSet::load(config_path.clone(), pwd) {
+        Err(Error::NoConfigFile) => None,
+        Err(e) => return Err(e),
+        Ok(s) => Some(s),
+    };
+    let mut new = match &new_set {
+        Some(set) => Some(Resolver::new(set).resolve(pwd)?),
+        None => None,
+    };
+
+    // The previous directory may be gone, it only matters for `on_leave`
+    let from = from.filter(|d| !d.as_os_str().is_empty());
+    let old_set = from
+        .as_ref()
+        .and_then(|d| ConfigSet::load(config_path, d).ok());
+    let mut old = match (&old_set, &from) {
+        (Some(set), Some(d)) => Resolver::new(set).resolve(d).ok(),
+        _ => None,
+    };
+
+    // Sections of files that are not allowed get no functions and run no hooks
+    for resolution in old.iter_mut().chain(new.iter_mut()) {
+        resolution
+            .candidates
+            .retain(|c| trust::is_allowed(&c.config.path, &c.config.text));
+    }
+    if let Some(set) = &new_set {
+        for config in &set.files {
+            if !trust::is_allowed(&config.path, &config.text) {
+                eprintln!(
+                    "dipse: {} is not allowed, run `dipse allow` to use its aliases",
+                    config.path.display()
+                );
+            }
+        }
+    }
+
+    let defined = funcs.split_whitespace().collect::<Vec<_>>();
+    Ok(hook(shell, old.as_ref(), new.as_ref(), &defined))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{model::Config, parser::parse_config, resolver::Candidate};
+
+    const TEXT: &str = r#"["."]
+b = "make"
+on_enter = "echo enter root"
+on_leave = "echo leave root"
+
+[sub]
+t = "make test"
+cd = "make cd"
+"x.y" = "make xy"
+on_enter = "echo enter sub"
+on_leave = "echo leave sub"
+"#;
+
+    fn config() -> Config {
+        parse_config(Path::new("/project/.d.toml"), TEXT).unwrap()
+    }
+
+    /// Resolution of `/project/<dir>` with the sections `keys` of `config`, nearest first
+    fn resolution<'a>(config: &'a Config, dir: &str, keys: &[&str]) -> Resolution<'a> {
+        Resolution {
+            pwd: Path::new("/project").join(dir),
+            candidates: keys
+                .iter()
+                .map(|key| Candidate {
+                    config,
+                    section: config.section(Path::new(key)).unwrap(),
+                    path: Path::new("/project").join(key),
+                    discovered: vec![],
+                })
+                .collect(),
+            warnings: vec![],
+        }
+    }
+
+    #[test]
+    fn init_script_calls_the_hook_of_its_shell() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = init_script(shell);
+            let call = format!(
+                "command dipse __hook {} --from \"$_DIPSE_PWD\" --funcs \"$_DIPSE_FUNCS\"",
+                shell
+            );
+            assert!(script.contains(&call), "{}", script);
+        }
+        // Sourcing the rc file twice keeps a single hook
+        assert!(init_script(Shell::Bash).contains("*\";_dipse_hook;\"*) ;;"));
+    }
+
+    #[test]
+    fn hook_runs_leave_and_enter_for_changed_sections_only() {
+        let config = config();
+        let root = resolution(&config, ".", &["."]);
+        let sub = resolution(&config, "sub", &["sub", "."]);
+
+        let down = hook(Shell::Bash, Some(&root), Some(&sub), &[]);
+        assert!(down.ends_with("echo enter sub\n"));
+        assert!(!down.contains("root"));
+
+        let up = hook(Shell::Bash, Some(&sub), Some(&root), &[]);
+        assert!(up.starts_with("echo leave sub\n"));
+        assert!(!up.contains("enter"));
+
+        // Entering from nowhere runs the outermost section first
+        let fresh = hook(Shell::Bash, None, Some(&sub), &[]);
+        let enter_root = fresh.find("echo enter root").unwrap();
+        assert!(enter_root < fresh.find("echo enter sub").unwrap());
+    }
+
+    #[test]
+    fn hook_defines_functions_for_valid_names_only() {
+        let config = config();
+        let sub = resolution(&config, "sub", &["sub", "."]);
+        let code = hook(Shell::Bash, None, Some(&sub), &["old", "x.y"]);
+
+        assert!(code.contains(
+            "case \"$(typeset -f old 2>/dev/null)\" in *\"command dipse old --\"*) unset -f old ;; esac\n"
+        ));
+        assert!(code.contains(
+            "if ! type t >/dev/null 2>&1; then t() { command dipse t -- \"$@\"; }; _DIPSE_FUNCS=\"$_DIPSE_FUNCS t\"; fi\n"
+        ));
+        assert!(code.contains("then b() {"));
+        assert!(!code.contains("cd()"));
+        assert!(!code.contains("x.y"));
+    }
+
+    #[test]
+    fn hook_speaks_fish() {
+        let config = config();
+        let root = resolution(&config, ".", &["."]);
+        let code = hook(Shell::Fish, None, Some(&root), &["old"]);
+
+        assert!(code.contains(
+            "functions old 2>/dev/null | string match -q '*command dipse old --*'; and functions -e old\n"
+        ));
+        assert!(code.contains("set -g _DIPSE_FUNCS\n"));
+        assert!(code.contains(
+            "if not type -q b\n    function b; command dipse b -- $argv; end\n    set -a _DIPSE_FUNCS b\nend\n"
+        ));
+    }
+}
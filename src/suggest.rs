@@ -0,0 +1,121 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+/// Number of single character insertions, deletions and substitutions to turn `a` into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+
+    prev[b.len()]
+}
+
+/// Find `name` in `names`
+///
+/// With `prefix` a name that is the start of exactly one alias resolves to that alias.
+pub fn match_name<'a, I>(name: &str, names: I, prefix: bool) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut starts_with = vec![];
+    for n in names {
+        if n == name {
+            return Some(n);
+        }
+        if prefix && n.starts_with(name) {
+            starts_with.push(n);
+        }
+    }
+
+    if starts_with.len() == 1 {
+        return Some(starts_with[0]);
+    }
+    None
+}
+
+/// Names in `names` that `name` is probably a typo or an abbreviation of, closest first
+pub fn suggestions<'a, I>(name: &str, names: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    let mut close = names
+        .into_iter()
+        .filter_map(|n| {
+            let distance = edit_distance(name, n);
+            if distance <= max_distance || n.starts_with(name) {
+                Some((distance, n))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    close.sort();
+    close.dedup();
+
+    close
+        .into_iter()
+        .take(3)
+        .map(|(_, n)| n.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 4] = ["build", "bench", "test", "t"];
+
+    #[test]
+    fn match_name_prefers_exact_names() {
+        assert_eq!(match_name("t", NAMES, true), Some("t"));
+        assert_eq!(match_name("test", NAMES, false), Some("test"));
+    }
+
+    #[test]
+    fn match_name_takes_unique_prefixes_if_enabled() {
+        assert_eq!(match_name("bu", NAMES, true), Some("build"));
+        assert_eq!(match_name("bu", NAMES, false), None);
+        assert_eq!(match_name("b", NAMES, true), None);
+        assert_eq!(match_name("x", NAMES, true), None);
+    }
+
+    #[test]
+    fn suggestions_for_typos_and_abbreviations() {
+        assert_eq!(suggestions("buld", NAMES), ["build"]);
+        assert_eq!(suggestions("tst", NAMES), ["test"]);
+        assert_eq!(suggestions("b", NAMES), ["t", "bench", "build"]);
+        assert!(suggestions("deploy", NAMES).is_empty());
+    }
+
+    #[test]
+    fn edit_distance_counts_single_changes() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+}
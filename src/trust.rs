@@ -0,0 +1,258 @@
+/*
+ * DIPSE (Directory Independent Project Script Executor)
+ * Copyright (C) 2021 DevHyperCoder
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! A project `.d.toml` comes with the repository it is in, so a freshly cloned project could
+//! run anything. Before aliases of a config file are run, the SHA-256 hash of its contents has
+//! to be on the allowlist in the data directory, which `dipse allow` adds it to. The allowed
+//! text is kept too, so a changed file can be shown as a diff against it. The global config is
+//! always trusted.
+//!
+//! Only the text of the `.d.toml` is hashed. Executables in `scripts_dir` and the files aliases
+//! are discovered from (`Makefile`, `package.json`, ...) are not, so changes to them run without
+//! a new `dipse allow`.
+
+use crate::{
+    config::{get_global_config_path, write_atomic, Lock},
+    error::Error,
+    model::Config,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+/// Lines shown around each change of a diff
+const CONTEXT: usize = 2;
+/// Larger files are not diffed line by line, the old and new text are shown in full
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Allowed version of a config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Allowed {
+    /// SHA-256 of the contents, in hex
+    sha256: String,
+    /// The contents, to show what changed
+    text: String,
+}
+
+/// Path of the allowlist, `$XDG_DATA_HOME/dipse/allowed.json`
+/// Error:
+/// - Data directory could not be found
+pub fn allowlist_path() -> Result<PathBuf, Error> {
+    match dirs::data_dir() {
+        Some(d) => Ok(d.join("dipse").join("allowed.json")),
+        None => Err(Error::DataDir),
+    }
+}
+
+/// SHA-256 of `text` in hex
+pub fn sha256(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Canonical path, the key of the allowlist
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Allowed files by canonical path
+/// Error:
+/// - The allowlist exists but could not be read or parsed
+fn read(path: &Path) -> Result<BTreeMap<PathBuf, Allowed>, Error> {
+    let text = match fs::read_to_string(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(Error::Allowlist(path.to_path_buf(), e)),
+        Ok(t) => t,
+    };
+    match serde_json::from_str(&text) {
+        Err(e) => Err(Error::Allowlist(
+            path.to_path_buf(),
+            io::Error::new(ErrorKind::InvalidData, e),
+        )),
+        Ok(a) => Ok(a),
+    }
+}
+
+/// Whether the global config is at `path`
+fn is_global(path: &Path) -> bool {
+    get_global_config_path().is_ok_and(|g| canonical(&g) == canonical(path))
+}
+
+/// Whether the config file at `path` with the contents `text` may run aliases
+///
+/// An allowlist that can not be read allows nothing.
+pub fn is_allowed(path: &Path, text: &str) -> bool {
+    if is_global(path) {
+        return true;
+    }
+    let list = match allowlist_path().and_then(|p| read(&p)) {
+        Err(_) => return false,
+        Ok(l) => l,
+    };
+    list.get(&canonical(path))
+        .is_some_and(|a| a.sha256 == sha256(text))
+}
+
+/// Refuse a config file that is not allowed with its current contents
+/// Error:
+/// - The file is new or changed since it was allowed, with a diff to the allowed version
+pub fn check(config: &Config) -> Result<(), Error> {
+    if is_allowed(&config.path, &config.text) {
+        return Ok(());
+    }
+    let old = allowlist_path()
+        .and_then(|p| read(&p))
+        .ok()
+        .and_then(|mut l| l.remove(&canonical(&config.path)))
+        .map(|a| a.text);
+    let diff = diff(old.as_deref().unwrap_or(""), &config.text);
+    Err(Error::NotAllowed(config.path.clone(), old.is_some(), diff))
+}
+
+/// Put the config file at `path` with the contents `text` on the allowlist
+/// Error:
+/// - The allowlist could not be locked, read or written
+pub fn allow(path: &Path, text: &str) -> Result<(), Error> {
+    if is_global(path) {
+        return Ok(());
+    }
+    let list_path = allowlist_path()?;
+    if let Some(dir) = list_path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            return Err(Error::Allowlist(list_path, e));
+        }
+    }
+    let _lock = Lock::acquire(&list_path)?;
+
+    let mut list = read(&list_path)?;
+    list.insert(
+        canonical(path),
+        Allowed {
+            sha256: sha256(text),
+            text: text.to_string(),
+        },
+    );
+    let json = serde_json::to_string_pretty(&list).unwrap_or_default();
+    match write_atomic(&list_path, &json) {
+        Err(e) => Err(Error::Allowlist(list_path, e)),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Line diff of `old` and `new`, `-` for removed and `+` for added lines with some context
+pub fn diff(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // Lines as (mark, text), from the longest common subsequence
+    let mut lines = vec![];
+    if (old.len() + 1) * (new.len() + 1) > MAX_DIFF_CELLS {
+        lines.extend(old.iter().map(|l| ('-', *l)));
+        lines.extend(new.iter().map(|l| ('+', *l)));
+    } else {
+        let width = new.len() + 1;
+        let mut lcs = vec![0usize; (old.len() + 1) * width];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i * width + j] = match old[i] == new[j] {
+                    true => lcs[(i + 1) * width + j + 1] + 1,
+                    false => lcs[(i + 1) * width + j].max(lcs[i * width + j + 1]),
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                lines.push((' ', old[i]));
+                i += 1;
+                j += 1;
+            } else if i < old.len()
+                && (j == new.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+            {
+                lines.push(('-', old[i]));
+                i += 1;
+            } else {
+                lines.push(('+', new[j]));
+                j += 1;
+            }
+        }
+    }
+
+    let changed = |idx: usize| lines.get(idx).is_some_and(|(m, _)| *m != ' ');
+    let mut out = String::new();
+    let mut skipped = false;
+    for (idx, (mark, line)) in lines.iter().enumerate() {
+        let near = (idx.saturating_sub(CONTEXT)..=idx + CONTEXT).any(changed);
+        if !near {
+            skipped = true;
+            continue;
+        }
+        if skipped && !out.is_empty() {
+            out.push_str("  ...\n");
+        }
+        skipped = false;
+        out.push_str(&format!("{} {}\n", mark, line));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_of_a_new_file_adds_every_line() {
+        assert_eq!(
+            diff("", "[\".\"]\nb = \"make\"\n"),
+            "+ [\".\"]\n+ b = \"make\"\n"
+        );
+    }
+
+    #[test]
+    fn diff_removes_before_it_adds() {
+        let old = "[\".\"]\nb = \"make\"\n";
+        let new = "[\".\"]\nb = \"curl x | sh\"\n";
+        assert_eq!(
+            diff(old, new),
+            "  [\".\"]\n- b = \"make\"\n+ b = \"curl x | sh\"\n"
+        );
+    }
+
+    #[test]
+    fn diff_shows_context_around_changes_only() {
+        let old = (1..=12).map(|i| format!("{}\n", i)).collect::<String>();
+        let new = old.replacen("2\n", "two\n", 1).replace("11\n", "eleven\n");
+
+        assert_eq!(
+            diff(&old, &new),
+            "  1\n- 2\n+ two\n  3\n  4\n  ...\n  9\n  10\n- 11\n+ eleven\n  12\n"
+        );
+    }
+
+    #[test]
+    fn diff_of_equal_texts_is_empty() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), "");
+    }
+}
@@ -16,10 +16,14 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{env::current_dir, fmt::Display, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    env::current_dir,
+    fmt::Display,
+    path::{Component, Path, PathBuf},
+};
 
 use crate::error::Error;
-use std::process::{Command, Output, Stdio};
 
 /// Get current directory ($PWD)
 pub fn get_current_dir() -> Result<PathBuf, Error> {
@@ -29,12 +33,63 @@ pub fn get_current_dir() -> Result<PathBuf, Error> {
     }
 }
 
+/// Remove `.` and `..` from `path` without touching the file system
+///
+/// Unlike `canonicalize`, this works for paths that do not exist (anymore).
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            c => normal.push(c),
+        }
+    }
+    normal
+}
+
+/// Empty directory for the test `name`, left over files of an earlier run are removed
+#[cfg(test)]
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dipse-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.canonicalize().unwrap()
+}
+
+/// How a command is started
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Launch {
+    /// `cmd_str` and the params are run through `sh -c`
+    #[default]
+    Shell,
+    /// The file is executed directly, params are its arguments
+    File(PathBuf),
+    /// The script is written to a temporary file that is run with the interpreter, params are
+    /// its arguments
+    Script {
+        /// Program and arguments running the script, the script itself if `None`
+        interpreter: Option<String>,
+        /// Text of the script
+        body: String,
+    },
+}
+
 /// Command string and params to append to it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct CommandParams {
     /// Command name
     pub cmd_str: String,
     /// Parameters for command
     pub params: Vec<String>,
+    /// Extra environment variables
+    pub env: BTreeMap<String, String>,
+    /// How the command is started
+    pub launch: Launch,
+    /// Question to ask before running the command, see [`crate::model::Alias::confirm`]
+    pub confirm: Option<String>,
 }
 
 impl Display for CommandParams {
@@ -46,18 +101,3 @@ impl Display for CommandParams {
         }
     }
 }
-
-/// Execute command with io inherited
-pub fn exec_command(cmd_params: CommandParams) -> Result<Output, Error> {
-    match Command::new("sh")
-        .arg("-c")
-        .arg(format!("{}", cmd_params))
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()
-    {
-        Ok(e) => Ok(e),
-        Err(e) => Err(Error::Command(e)),
-    }
-}